@@ -2,8 +2,8 @@
 
 mod tg;
 
-use crate::tg::types::{CacheSeenChatCallback, ChatType, IncomingMessageCallback, LoadChatsCallback, NativePackedChat, NativeSeenChat, UpdateChatCallback, UpdateUploadProgressCallback};
-use crate::tg::types::{LoginState, NativeChat, NativeMessage};
+use crate::tg::types::{CacheSeenChatCallback, ChatDraftCallback, ChatTypingCallback, ScheduledMessagesCallback, CatchUpCallback, ChatNotifySettingsCallback, MessageReactionsCallback, PollCallback, ChatType, IncomingMessageCallback, LoadChatsCallback, NativeOutboxMessage, NativePackedChat, NativeSeenChat, OutboxCallback, ThrottleCallback, ConnectionState, ConnectionStateCallback, LifecycleState, LifecycleStateCallback, UpdateChatCallback, UpdateUploadProgressCallback};
+use crate::tg::types::{LoginState, NativeChatAction, NativeSendOptions, NativeWebPage, NativeAnimation, NativeDocumentRef, NativeSticker, NativeStickerSet, NativeStickerSetRef, NativeContact, NativeLocation, NativePoll, NativePollDraft, NativePollVoters, NativeInlineResults, NativeBotCallbackAnswer, NativeAvailableReactions, NativeReaction, NativeReactionCount, NativeChat, NativeMessage, NativeProxyConfig, NativeDifferenceSummary, NativePeerNotifySettings, NativeProxyTestResult, NativePushNotification, NativeSearchHit};
 use grammers_session::PackedChat;
 use hilog::{Builder, LogDomain};
 use log::{debug, error, LevelFilter};
@@ -19,20 +19,85 @@ use tokio_util::sync::CancellationToken;
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Set the 32-byte key the session is encrypted with at rest. It has to be called
+/// before any other API, since the first call loads the session.
 #[napi]
-pub async fn is_logged_in() -> bool {
-    tg::Backend::get_instance().await.is_logged_in().await
+pub fn set_session_encryption_key(key: Buffer) -> Result<()> {
+    tg::session::set_session_key(key.as_ref()).map_err(|e| Error::from_reason(e.to_string()))
 }
 
 #[napi]
-pub async fn register_device(token: String) -> Result<bool> {
+pub async fn is_logged_in() -> Result<bool> {
     tg::Backend::get_instance()
         .await
-        .register_device(token)
+        .is_logged_in()
         .await
         .map_err(|e| Error::from_reason(e.to_string()))
 }
 
+#[napi]
+pub async fn register_device(token: String, no_muted: Option<bool>, other_uids: Option<Vec<i64>>) -> Result<bool> {
+    tg::Backend::get_instance()
+        .await
+        .register_device(token, no_muted.unwrap_or(false), other_uids.unwrap_or_default())
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn unregister_device(token: String, other_uids: Option<Vec<i64>>) -> Result<bool> {
+    tg::Backend::get_instance()
+        .await
+        .unregister_device(token, other_uids.unwrap_or_default())
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn get_notify_settings(chat_id: i64) -> Result<NativePeerNotifySettings> {
+    tg::Backend::get_instance()
+        .await
+        .get_notify_settings(chat_id)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Mute the chat until the unix time `until`, or forever.
+#[napi]
+pub async fn mute_chat(chat_id: i64, until: Option<i32>) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .mute_chat(chat_id, until)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn unmute_chat(chat_id: i64) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .unmute_chat(chat_id)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn update_notify_settings(chat_id: i64, settings: NativePeerNotifySettings) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .update_notify_settings(chat_id, settings)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Decrypt the payload of a push received through the token given to `register_device`.
+/// It doesn't need the backend, so it can run in the push extension, but the push secret
+/// is stored encrypted with the session key: call `set_session_encryption_key` first.
+#[napi]
+pub fn decrypt_push_payload(payload: Buffer) -> Result<NativePushNotification> {
+    tg::push::decrypt_push_payload(payload.as_ref()).map_err(|e| Error::from_reason(e.to_string()))
+}
+
 #[napi]
 pub async fn login(phone_number: String) -> Result<LoginState> {
     tg::Backend::get_instance()
@@ -71,33 +136,448 @@ pub async fn sign_out() -> bool {
 #[napi]
 pub async fn run() -> Result<()> {
     debug!("homo::run() called");
-    if let Some(handler) = tg::Backend::get_instance().await.get_run_handler().await {
-        if !handler.is_finished() {
-            return Err(Error::from_reason("homo::tg::Backend::run() already "));
-        }
-    }
-    let handler = tokio::spawn(tg::Backend::get_instance().await.run());
-    tg::Backend::get_instance()
-        .await
-        .set_run_handler(handler)
-        .await;
-    Ok(())
+    start().await
 }
 
 #[napi]
 pub async fn stop() {
     debug!("homo::stop() called");
-    match tg::Backend::get_instance().await.get_run_handler().await {
-        Some(handler) => {
-            handler.abort();
-            debug!("homo::tg::Backend::run_handler aborted")
-            // no need to drop manually
-            // std::mem::drop(handler);
-        }
-        None => {
-            error!("homo::tg::Backend::run() already stopped");
-        }
-    }
+    shutdown().await
+}
+
+#[napi]
+pub async fn start() -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .start()
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// To be called when the app goes to the background.
+#[napi]
+pub async fn pause() -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .pause()
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// To be called when the app comes back to the foreground.
+#[napi]
+pub async fn resume() -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .resume()
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn shutdown() {
+    tg::Backend::get_instance().await.shutdown().await
+}
+
+#[napi]
+pub async fn get_lifecycle_state() -> LifecycleState {
+    tg::Backend::get_instance().await.get_lifecycle_state()
+}
+
+#[napi]
+pub async fn register_lifecycle_state_callback(cb: LifecycleStateCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_lifecycle_state_callback(cb);
+}
+
+#[napi]
+pub async fn register_catch_up_callback(cb: CatchUpCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_catch_up_callback(cb);
+}
+
+#[napi]
+pub async fn register_chat_notify_settings_callback(cb: ChatNotifySettingsCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_chat_notify_settings_callback(cb);
+}
+
+#[napi]
+pub async fn register_message_reactions_callback(cb: MessageReactionsCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_message_reactions_callback(cb);
+}
+
+/// Replace my reactions to the message, an empty list removes them.
+#[napi]
+pub async fn send_reaction(chat_id: i64, msg_id: i32, reactions: Vec<NativeReaction>, big: Option<bool>) -> Result<Vec<NativeReactionCount>> {
+    tg::Backend::get_instance()
+        .await
+        .send_reaction(chat_id, msg_id, reactions, big.unwrap_or(false))
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Press a `Callback` button of the message's reply markup, with the button's `data`.
+#[napi]
+pub async fn press_button(chat_id: i64, msg_id: i32, data: Vec<u8>) -> Result<NativeBotCallbackAnswer> {
+    tg::Backend::get_instance()
+        .await
+        .press_button(chat_id, msg_id, data)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Ask `@bot_username` for the inline results of `query`, as typed in the composer.
+#[napi]
+pub async fn inline_query(bot_username: String, chat_id: i64, query: String, offset: Option<String>) -> Result<NativeInlineResults> {
+    tg::Backend::get_instance()
+        .await
+        .inline_query(bot_username, chat_id, query, offset)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn send_inline_result(chat_id: i64, query_id: i64, result_id: String) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_inline_result(chat_id, query_id, result_id)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn register_poll_callback(cb: PollCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_poll_callback(cb);
+}
+
+#[napi]
+pub async fn register_chat_draft_callback(cb: ChatDraftCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_chat_draft_callback(cb);
+}
+
+/// Save the unfinished message of the chat, to pick it up on another device.
+#[napi]
+pub async fn save_draft(chat_id: i64, text: String, reply_to: Option<i32>) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .save_draft(chat_id, text, reply_to)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn clear_draft(chat_id: i64) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .clear_draft(chat_id)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn register_scheduled_messages_callback(cb: ScheduledMessagesCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_scheduled_messages_callback(cb);
+}
+
+#[napi]
+pub async fn get_scheduled_messages(chat_id: i64) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .get_scheduled_messages(chat_id)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn reschedule_message(chat_id: i64, msg_id: i32, date: i64) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .reschedule_message(chat_id, msg_id, date)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn send_scheduled_now(chat_id: i64, ids: Vec<i32>) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_scheduled_now(chat_id, ids)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn delete_scheduled_messages(chat_id: i64, ids: Vec<i32>) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .delete_scheduled_messages(chat_id, ids)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn register_chat_typing_callback(cb: ChatTypingCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_chat_typing_callback(cb);
+}
+
+/// Show what I am doing in the chat, repeat it every few seconds while it lasts.
+#[napi]
+pub async fn send_chat_action(chat_id: i64, action: NativeChatAction) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .send_chat_action(chat_id, action)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn send_poll(chat_id: i64, poll: NativePollDraft) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_poll(chat_id, poll)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Vote for the answers at the given indexes, an empty list retracts the vote.
+#[napi]
+pub async fn vote_poll(chat_id: i64, msg_id: i32, answers: Vec<u32>) -> Result<NativePoll> {
+    tg::Backend::get_instance()
+        .await
+        .vote_poll(chat_id, msg_id, answers)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn close_poll(chat_id: i64, msg_id: i32) -> Result<NativePoll> {
+    tg::Backend::get_instance()
+        .await
+        .close_poll(chat_id, msg_id)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn get_poll_voters(chat_id: i64, msg_id: i32, answer: Option<u32>, offset: Option<String>, limit: Option<i32>) -> Result<NativePollVoters> {
+    tg::Backend::get_instance()
+        .await
+        .get_poll_voters(chat_id, msg_id, answer, offset, limit.unwrap_or(50))
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Send a point on the map, or a venue if `location.venue` is set.
+#[napi]
+pub async fn send_location(chat_id: i64, location: NativeLocation) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_location(chat_id, location)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn send_contact(chat_id: i64, contact: NativeContact) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_contact(chat_id, contact)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn send_dice(chat_id: i64, emoticon: String) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_dice(chat_id, emoticon)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Share the live location for `period` seconds.
+#[napi]
+pub async fn send_live_location(chat_id: i64, location: NativeLocation, period: i32) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_live_location(chat_id, location, period)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Report a new position, the message is edited at most every 30 seconds.
+#[napi]
+pub async fn update_live_location(chat_id: i64, msg_id: i32, location: NativeLocation) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .update_live_location(chat_id, msg_id, location)
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn stop_live_location(chat_id: i64, msg_id: i32) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .stop_live_location(chat_id, msg_id)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn get_installed_sticker_sets() -> Result<Vec<NativeStickerSet>> {
+    tg::Backend::get_instance()
+        .await
+        .get_installed_sticker_sets()
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Fetch a sticker set with its stickers, e.g. by the short name of a `t.me/addstickers/` link.
+#[napi]
+pub async fn get_sticker_set(set: NativeStickerSetRef) -> Result<NativeStickerSet> {
+    tg::Backend::get_instance()
+        .await
+        .get_sticker_set(set)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn install_sticker_set(set: NativeStickerSetRef, archived: Option<bool>) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .install_sticker_set(set, archived.unwrap_or(false))
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn uninstall_sticker_set(set: NativeStickerSetRef) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .uninstall_sticker_set(set)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn get_recent_stickers() -> Result<Vec<NativeSticker>> {
+    tg::Backend::get_instance()
+        .await
+        .get_recent_stickers()
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn get_favorite_stickers() -> Result<Vec<NativeSticker>> {
+    tg::Backend::get_instance()
+        .await
+        .get_favorite_stickers()
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Resolve the custom emoji ids found in message entities.
+#[napi]
+pub async fn get_custom_emoji_documents(ids: Vec<i64>) -> Result<Vec<NativeSticker>> {
+    tg::Backend::get_instance()
+        .await
+        .get_custom_emoji_documents(ids)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn send_sticker(chat_id: i64, document: NativeDocumentRef) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_sticker(chat_id, document)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Download the sticker or custom emoji and return the path of the file.
+#[napi]
+pub async fn download_sticker(sticker: NativeSticker) -> Result<String> {
+    tg::Backend::get_instance()
+        .await
+        .download_sticker(sticker)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn get_saved_gifs() -> Result<Vec<NativeAnimation>> {
+    tg::Backend::get_instance()
+        .await
+        .get_saved_gifs()
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn save_gif(document: NativeDocumentRef) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .save_gif(document, false)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn unsave_gif(document: NativeDocumentRef) -> Result<()> {
+    tg::Backend::get_instance()
+        .await
+        .save_gif(document, true)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn send_saved_gif(chat_id: i64, document: NativeDocumentRef) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_saved_gif(chat_id, document)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn get_available_reactions(chat_id: i64) -> Result<NativeAvailableReactions> {
+    tg::Backend::get_instance()
+        .await
+        .get_available_reactions(chat_id)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn is_catching_up() -> bool {
+    tg::Backend::get_instance().await.is_catching_up()
+}
+
+/// Fetch the updates missed since the last saved state, to be called on resume.
+#[napi]
+pub async fn force_get_difference() -> Result<NativeDifferenceSummary> {
+    tg::Backend::get_instance()
+        .await
+        .force_get_difference()
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Persist the session and caches right away, to be called when the app goes to the
+/// background.
+#[napi]
+pub async fn flush() {
+    tg::Backend::get_instance().await.flush().await;
 }
 
 #[napi]
@@ -123,6 +603,24 @@ pub async fn register_incoming_message_callback(cb: IncomingMessageCallback) {
     let backend = tg::Backend::get_instance().await;
     backend.register_incoming_message_callback(cb);
 }
+#[napi]
+pub async fn register_outbox_callback(cb: OutboxCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_outbox_callback(cb);
+}
+
+#[napi]
+pub async fn register_throttle_callback(cb: ThrottleCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_throttle_callback(cb);
+}
+
+/// Flood waits longer than `seconds` fail right away instead of being waited out.
+#[napi]
+pub async fn set_flood_sleep_threshold(seconds: u32) {
+    tg::Backend::get_instance().await.set_flood_sleep_threshold(seconds);
+}
+
 #[napi]
 pub async fn load_chats() -> Result<()> {
     let backend = tg::Backend::get_instance().await;
@@ -169,13 +667,84 @@ pub async fn sync_caches_from_local_db(
 }
 
 #[napi]
-pub async fn send_message(chat_id: i64, text: String, medias: Option<Vec<String>>, update_upload_progress_callback: UpdateUploadProgressCallback) -> Result<Vec<NativeMessage>> {
-    let backend = tg::Backend::get_instance().await;
-    let messages = backend
-        .send_message(chat_id, text, medias, Arc::new(update_upload_progress_callback))
+pub async fn get_cached_chats() -> Vec<NativeChat> {
+    tg::Backend::get_instance().await.get_cached_chats()
+}
+
+#[napi]
+pub async fn get_cached_seen_chats() -> Vec<NativeSeenChat> {
+    tg::Backend::get_instance().await.get_cached_seen_chats()
+}
+
+#[napi]
+pub async fn get_cached_messages(chat_id: i64) -> Vec<NativeMessage> {
+    tg::Backend::get_instance().await.get_cached_messages(chat_id)
+}
+
+/// Queue a message into the outbox and resolve right away with it as pending, even
+/// while offline. The outbox callback maps its `temp_id` to the sent messages once it is
+/// delivered, or reports it failed, to be retried or discarded.
+#[napi]
+pub async fn send_message(chat_id: i64, text: String, medias: Option<Vec<String>>, update_upload_progress_callback: UpdateUploadProgressCallback, options: Option<NativeSendOptions>) -> NativeOutboxMessage {
+    tg::Backend::get_instance()
         .await
-        .map_err(|e| Error::from_reason(e.to_string()))?;
-    Ok(messages)
+        .queue_message(chat_id, text, medias, options, Some(Arc::new(update_upload_progress_callback)))
+}
+
+/// Preview the first link of `text` while composing.
+#[napi]
+pub async fn get_web_preview(text: String) -> Result<Option<NativeWebPage>> {
+    tg::Backend::get_instance()
+        .await
+        .get_web_preview(text)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Send an OGG/Opus recording as a voice message. The audio is not decoded: its waveform
+/// is approximated from the sizes of the Opus packets, which follow the loudness.
+#[napi]
+pub async fn send_voice(chat_id: i64, path: String, caption: Option<String>, update_upload_progress_callback: UpdateUploadProgressCallback) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_voice(chat_id, path, caption.unwrap_or_default(), Some(Arc::new(update_upload_progress_callback)))
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Send a square MP4 recording as a round video message.
+#[napi]
+pub async fn send_video_note(chat_id: i64, path: String, update_upload_progress_callback: UpdateUploadProgressCallback) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_video_note(chat_id, path, Some(Arc::new(update_upload_progress_callback)))
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Send an MP4 file as a GIF.
+#[napi]
+pub async fn send_animation(chat_id: i64, path: String, caption: Option<String>, update_upload_progress_callback: UpdateUploadProgressCallback) -> Result<Vec<NativeMessage>> {
+    tg::Backend::get_instance()
+        .await
+        .send_animation(chat_id, path, caption.unwrap_or_default(), Some(Arc::new(update_upload_progress_callback)))
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn get_outbox_messages(chat_id: Option<i64>) -> Vec<NativeOutboxMessage> {
+    tg::Backend::get_instance().await.get_outbox_messages(chat_id)
+}
+
+#[napi]
+pub async fn retry_outbox_message(temp_id: i64) -> bool {
+    tg::Backend::get_instance().await.retry_outbox_message(temp_id)
+}
+
+#[napi]
+pub async fn discard_outbox_message(temp_id: i64) -> bool {
+    tg::Backend::get_instance().await.discard_outbox_message(temp_id)
 }
 
 #[napi]
@@ -208,4 +777,75 @@ pub async fn get_chat_photo_thumb(chat_id: i64) -> Result<Option<Buffer>> {
 #[napi]
 pub async fn reconnect() -> Result<bool> {
     Ok(tg::Backend::get_instance().await.reconnect().await)
-}
\ No newline at end of file
+}
+
+/// Drop the connection and open a new one, e.g. once the state is `Disconnected`.
+#[napi]
+pub async fn restart_connection() -> Result<()> {
+    tg::Backend::get_shared_instance()
+        .await
+        .restart_connection()
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn register_connection_state_callback(cb: ConnectionStateCallback) {
+    let backend = tg::Backend::get_instance().await;
+    backend.register_connection_state_callback(cb);
+}
+
+#[napi]
+pub async fn get_connection_state() -> ConnectionState {
+    tg::Backend::get_instance().await.get_connection_state()
+}
+
+/// Exponential backoff with jitter, starting at `base_delay_ms` and capped at
+/// `max_delay_ms`. Retries forever unless `max_attempts` is given.
+#[napi]
+pub async fn set_reconnect_policy(base_delay_ms: u32, max_delay_ms: u32, max_attempts: Option<u32>) {
+    tg::Backend::get_instance()
+        .await
+        .set_reconnect_policy(base_delay_ms, max_delay_ms, max_attempts);
+}
+
+/// Hook for the OS network callback.
+#[napi]
+pub async fn set_network_available(available: bool) {
+    tg::Backend::get_instance().await.set_network_available(available);
+}
+
+#[napi]
+pub async fn search_messages(query: String, chat_id: Option<i64>, limit: Option<u32>) -> Result<Vec<NativeSearchHit>> {
+    let backend = tg::Backend::get_instance().await;
+    Ok(backend.search_messages(&query, chat_id, limit.unwrap_or(50) as usize))
+}
+
+#[napi]
+pub async fn get_proxy() -> Option<NativeProxyConfig> {
+    tg::Backend::get_instance().await.get_proxy()
+}
+
+/// Switch to `config` and reconnect, staying logged in.
+#[napi]
+pub async fn set_proxy(config: NativeProxyConfig) -> Result<()> {
+    tg::Backend::get_shared_instance()
+        .await
+        .set_proxy(Some(config))
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn clear_proxy() -> Result<()> {
+    tg::Backend::get_shared_instance()
+        .await
+        .set_proxy(None)
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+#[napi]
+pub async fn test_proxy(config: NativeProxyConfig) -> NativeProxyTestResult {
+    tg::Backend::get_instance().await.test_proxy(config).await
+}
@@ -0,0 +1,73 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{NativeAnimation, NativeDocumentRef, NativeMessage, UpdateUploadProgressCallback};
+use crate::tg::voice::{parse_mp4, Mp4Info};
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use log::debug;
+use std::sync::Arc;
+
+impl Backend {
+    pub async fn get_saved_gifs(&self) -> Result<Vec<NativeAnimation>> {
+        let request = tl::functions::messages::GetSavedGifs { hash: 0 };
+        match scheduled!(self, "messages.getSavedGifs", self.client().invoke(&request))? {
+            tl::enums::messages::SavedGifs::Gifs(saved) => Ok(saved
+                .gifs
+                .iter()
+                .filter_map(|document| match document {
+                    tl::enums::Document::Document(document) => NativeAnimation::from_document(document),
+                    tl::enums::Document::Empty(_) => None,
+                })
+                .collect()),
+            tl::enums::messages::SavedGifs::NotModified => Ok(Vec::new()),
+        }
+    }
+
+    /// Add the GIF to the saved ones, or remove it if `unsave` is set.
+    pub async fn save_gif(&self, document: NativeDocumentRef, unsave: bool) -> Result<()> {
+        let request = tl::functions::messages::SaveGif {
+            id: document.to_input(),
+            unsave,
+        };
+        scheduled!(self, "messages.saveGif", self.client().invoke(&request))?;
+        Ok(())
+    }
+
+    /// Upload the MP4 file at `path` and send it as a GIF.
+    pub async fn send_animation(
+        &self,
+        chat_id: i64,
+        path: String,
+        caption: String,
+        update_upload_progress_callback: Option<Arc<UpdateUploadProgressCallback>>,
+    ) -> Result<Vec<NativeMessage>> {
+        let Mp4Info { duration, width, height } = parse_mp4(&std::fs::read(&path)?)?;
+        debug!("send_animation {path}: {duration:.1}s, {width}x{height}");
+        let file_name = std::path::Path::new(&path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("animation.mp4")
+            .to_string();
+        let attributes = vec![
+            tl::enums::DocumentAttribute::Animated,
+            tl::types::DocumentAttributeVideo {
+                round_message: false,
+                supports_streaming: true,
+                nosound: true,
+                duration,
+                w: width,
+                h: height,
+                preload_prefix_size: None,
+                video_start_ts: None,
+            }
+            .into(),
+            tl::types::DocumentAttributeFilename { file_name }.into(),
+        ];
+        self.send_uploaded_document(chat_id, &path, "video/mp4", attributes, true, caption, update_upload_progress_callback)
+            .await
+    }
+
+    pub async fn send_saved_gif(&self, chat_id: i64, document: NativeDocumentRef) -> Result<Vec<NativeMessage>> {
+        self.send_document(chat_id, &document, String::new()).await
+    }
+}
@@ -0,0 +1,29 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::NativeBotCallbackAnswer;
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use log::debug;
+
+impl Backend {
+    /// Press the callback button carrying `data` under the message `msg_id`, and return
+    /// what the bot answered. Buttons that require the 2FA password are not supported.
+    pub async fn press_button(&self, chat_id: i64, msg_id: i32, data: Vec<u8>) -> Result<NativeBotCallbackAnswer> {
+        debug!("press_button {chat_id}/{msg_id}");
+        let request = tl::functions::messages::GetBotCallbackAnswer {
+            game: false,
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            msg_id,
+            data: Some(data),
+            password: None,
+        };
+        let tl::enums::messages::BotCallbackAnswer::Answer(answer) =
+            scheduled!(self, "messages.getBotCallbackAnswer", self.client().invoke(&request))?;
+        Ok(NativeBotCallbackAnswer {
+            message: answer.message,
+            alert: answer.alert,
+            url: answer.url,
+            cache_time: answer.cache_time,
+        })
+    }
+}
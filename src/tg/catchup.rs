@@ -0,0 +1,345 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::proxy::proxy_url;
+use crate::tg::types::{CatchUpState, NativeCatchUpEvent, NativeDifferenceSummary};
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use grammers_client::session::Session;
+use grammers_client::types::{Chat, ChatMap};
+use log::{debug, info};
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// Once no update arrived for this long, we consider ourselves caught up.
+pub(crate) const CATCH_UP_IDLE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Messages sent at most this long before the update loop started still count as live.
+const LIVE_TOLERANCE_SECS: i64 = 5;
+/// How many updates are handled between two progress events.
+const CATCH_UP_PROGRESS_INTERVAL: u32 = 20;
+/// How many messages a single `updates.getChannelDifference` asks for.
+const CHANNEL_DIFFERENCE_LIMIT: i32 = 100;
+
+/// What `get_difference` fetched, applied to the session only once it is handled.
+struct Difference {
+    messages: Vec<tl::enums::Message>,
+    updates: Vec<tl::enums::Update>,
+    users: Vec<tl::enums::User>,
+    chats: Vec<tl::enums::Chat>,
+    state: tl::types::updates::State,
+    /// the pts each channel difference reached
+    channel_states: Vec<(i64, i32)>,
+    too_long: bool,
+}
+
+impl Backend {
+    fn emit_catch_up(&self, state: CatchUpState, behind_seconds: i64) {
+        if let Some(cb) = self.catch_up_callback.as_ref() {
+            cb.call(
+                Ok(NativeCatchUpEvent {
+                    state,
+                    processed: self.catch_up_processed.load(Ordering::Acquire),
+                    behind_seconds,
+                }),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+    }
+
+    #[inline]
+    pub fn is_catching_up(&self) -> bool {
+        self.catching_up.load(Ordering::Acquire)
+    }
+
+    /// Called when the update loop starts: whatever arrives first is the backlog.
+    pub(crate) fn begin_catch_up(&self) {
+        self.catch_up_started_at.store(chrono::Utc::now().timestamp(), Ordering::Release);
+        self.catch_up_processed.store(0, Ordering::Release);
+        if !self.catching_up.swap(true, Ordering::AcqRel) {
+            info!("begin_catch_up Catching up on missed updates");
+            self.emit_catch_up(CatchUpState::Started, 0);
+        }
+    }
+
+    pub(crate) fn finish_catch_up(&self) {
+        if self.catching_up.swap(false, Ordering::AcqRel) {
+            info!(
+                "finish_catch_up Caught up after {} updates",
+                self.catch_up_processed.load(Ordering::Acquire)
+            );
+            self.emit_catch_up(CatchUpState::Finished, 0);
+        }
+    }
+
+    /// Account for an update sent at `date` (if known) and tell whether it is live, as
+    /// opposed to part of the backlog we are catching up on.
+    pub(crate) fn track_update(&self, date: Option<i64>) -> bool {
+        if !self.is_catching_up() {
+            return true;
+        }
+        let started_at = self.catch_up_started_at.load(Ordering::Acquire);
+        if date.is_some_and(|date| date >= started_at - LIVE_TOLERANCE_SECS) {
+            self.finish_catch_up();
+            return true;
+        }
+        let processed = self.catch_up_processed.fetch_add(1, Ordering::AcqRel) + 1;
+        if processed % CATCH_UP_PROGRESS_INTERVAL == 0 {
+            let behind_seconds = date.map_or(0, |date| chrono::Utc::now().timestamp() - date);
+            debug!("track_update Caught up on {processed} updates, {behind_seconds}s behind");
+            self.emit_catch_up(CatchUpState::Progress, behind_seconds);
+        }
+        false
+    }
+
+    /// Ask Telegram for everything we missed since the state saved in the session, e.g.
+    /// when the app is resumed. The new messages are delivered as historical ones through
+    /// the incoming message callback, the other updates go through the handlers of `run`.
+    ///
+    /// The update loop is stopped meanwhile, and the client reconnected from the new state
+    /// afterwards so that grammers does not fetch the same difference again. The state is
+    /// only moved forward once the whole difference is handled.
+    pub async fn force_get_difference(&'static self) -> Result<NativeDifferenceSummary> {
+        let _guard = self.lifecycle_mutex.lock().await;
+        let was_running = self.is_update_loop_running();
+        self.stop_update_loop().await;
+        self.save_session().await;
+        let result = async {
+            let difference = self.get_difference().await?;
+            let summary = self.apply_difference(difference).await?;
+            // the new state has to be on disk before reconnecting from it
+            self.save_session().await;
+            let (proxy_url, bridge) = proxy_url(self.get_proxy().as_ref()).await?;
+            self.reconnect_client(proxy_url, bridge).await?;
+            Ok::<_, anyhow::Error>(summary)
+        }
+        .await;
+        if was_running {
+            self.start_update_loop();
+        }
+        let summary = result?;
+        info!("force_get_difference {:?}", summary);
+        Ok(summary)
+    }
+
+    /// Handle what `get_difference` fetched, then apply its state to the session.
+    async fn apply_difference(&'static self, difference: Difference) -> Result<NativeDifferenceSummary> {
+        let chats = self.cache_difference_chats(difference.users, difference.chats);
+        let mut messages = Vec::with_capacity(difference.messages.len());
+        for raw in difference.messages.iter() {
+            messages.extend(self.native_message_from_tl(raw).await?);
+        }
+        messages.sort_by_key(|message| message.timestamp);
+
+        let now = chrono::Utc::now().timestamp();
+        let summary = NativeDifferenceSummary {
+            new_messages: messages.len() as u32,
+            other_updates: difference.updates.len() as u32,
+            behind_seconds: messages.first().map_or(0, |message| now - message.timestamp),
+            too_long: difference.too_long,
+        };
+        self.begin_catch_up();
+        for update in difference.updates.iter() {
+            self.track_update(None);
+            self.raw_update_handler(update).await;
+        }
+        for message in messages {
+            self.track_update(Some(message.timestamp));
+            self.search_index.index_message(&message);
+            self.cache_recent_message(&message);
+            if let Some(message) = self.deliver_message_to_known_chat(message, false) {
+                match chats.get(&message.chat_id) {
+                    Some(chat) => self.deliver_message_to_new_chat(chat, message, false).await,
+                    None => debug!("apply_difference Skipping message of unresolved chat {}", message.chat_id),
+                }
+            }
+        }
+        self.finish_catch_up();
+
+        let client = self.client();
+        let session = client.session();
+        session.set_state(difference.state);
+        for (channel_id, pts) in difference.channel_states {
+            session.set_channel_state(channel_id, pts);
+        }
+        Ok(summary)
+    }
+
+    /// Remember the users and chats a difference came with, which its updates refer to.
+    fn cache_difference_chats(&self, users: Vec<tl::enums::User>, chats: Vec<tl::enums::Chat>) -> HashMap<i64, Chat> {
+        let peers: Vec<tl::enums::Peer> = users
+            .iter()
+            .map(|user| match user {
+                tl::enums::User::Empty(user) => user.id,
+                tl::enums::User::User(user) => user.id,
+            })
+            .map(|user_id| tl::enums::Peer::User(tl::types::PeerUser { user_id }))
+            .chain(chats.iter().map(|chat| match chat {
+                tl::enums::Chat::Empty(chat) => tl::enums::Peer::Chat(tl::types::PeerChat { chat_id: chat.id }),
+                tl::enums::Chat::Chat(chat) => tl::enums::Peer::Chat(tl::types::PeerChat { chat_id: chat.id }),
+                tl::enums::Chat::Forbidden(chat) => tl::enums::Peer::Chat(tl::types::PeerChat { chat_id: chat.id }),
+                tl::enums::Chat::Channel(chat) => tl::enums::Peer::Channel(tl::types::PeerChannel { channel_id: chat.id }),
+                tl::enums::Chat::ChannelForbidden(chat) => {
+                    tl::enums::Peer::Channel(tl::types::PeerChannel { channel_id: chat.id })
+                }
+            }))
+            .collect();
+        let chat_map = ChatMap::new(users, chats);
+        peers
+            .iter()
+            .filter_map(|peer| chat_map.get(peer))
+            .map(|chat| {
+                self.cache_seen_chat(chat);
+                (chat.id(), chat.clone())
+            })
+            .collect()
+    }
+
+    /// Fetch the difference until it is complete, and the difference of the channels
+    /// reported as too long on the way. Nothing is applied to the session yet.
+    async fn get_difference(&self) -> Result<Difference> {
+        let client = self.client();
+        let session = client.session();
+        let state = session
+            .get_state()
+            .ok_or_else(|| anyhow::anyhow!("No update state saved yet"))?;
+        let mut difference = Difference {
+            messages: Vec::new(),
+            updates: Vec::new(),
+            users: Vec::new(),
+            chats: Vec::new(),
+            state: tl::types::updates::State {
+                pts: state.pts,
+                qts: state.qts,
+                date: state.date,
+                seq: state.seq,
+                unread_count: 0,
+            },
+            channel_states: Vec::new(),
+            too_long: false,
+        };
+        loop {
+            let state = &mut difference.state;
+            let request = tl::functions::updates::GetDifference {
+                pts: state.pts,
+                pts_limit: None,
+                pts_total_limit: None,
+                date: state.date,
+                qts: state.qts,
+                qts_limit: None,
+            };
+            let done = match scheduled!(self, "updates.getDifference", self.client().invoke(&request))? {
+                tl::enums::updates::Difference::Empty(d) => {
+                    state.date = d.date;
+                    state.seq = d.seq;
+                    true
+                }
+                tl::enums::updates::Difference::Difference(d) => {
+                    let tl::enums::updates::State::State(new_state) = d.state;
+                    *state = new_state;
+                    difference.messages.extend(d.new_messages);
+                    difference.updates.extend(d.other_updates);
+                    difference.users.extend(d.users);
+                    difference.chats.extend(d.chats);
+                    true
+                }
+                tl::enums::updates::Difference::Slice(d) => {
+                    let tl::enums::updates::State::State(new_state) = d.intermediate_state;
+                    *state = new_state;
+                    difference.messages.extend(d.new_messages);
+                    difference.updates.extend(d.other_updates);
+                    difference.users.extend(d.users);
+                    difference.chats.extend(d.chats);
+                    false
+                }
+                tl::enums::updates::Difference::TooLong(d) => {
+                    state.pts = d.pts;
+                    difference.too_long = true;
+                    true
+                }
+            };
+            if done {
+                break;
+            }
+        }
+
+        let channels_too_long: Vec<tl::types::UpdateChannelTooLong> = difference
+            .updates
+            .iter()
+            .filter_map(|update| match update {
+                tl::enums::Update::ChannelTooLong(update) => Some(update.clone()),
+                _ => None,
+            })
+            .collect();
+        for update in channels_too_long {
+            self.get_channel_difference(session, &update, &mut difference).await?;
+        }
+        Ok(difference)
+    }
+
+    /// Fetch the difference of the channel `update` reports a gap in, until it is final,
+    /// into `difference`.
+    async fn get_channel_difference(
+        &self,
+        session: &Session,
+        update: &tl::types::UpdateChannelTooLong,
+        difference: &mut Difference,
+    ) -> Result<()> {
+        let access_hash = match self.seen_packed_chats_map.get(&update.channel_id) {
+            Some(packed_chat) => packed_chat.access_hash,
+            // a channel we hear of in this very difference
+            None => difference.chats.iter().find_map(|chat| match chat {
+                tl::enums::Chat::Channel(channel) if channel.id == update.channel_id => channel.access_hash,
+                _ => None,
+            }),
+        };
+        let Some(access_hash) = access_hash else {
+            debug!("get_channel_difference Skipping unknown channel {}", update.channel_id);
+            return Ok(());
+        };
+        let Some(mut pts) = session.get_channel_state(update.channel_id).or(update.pts) else {
+            debug!("get_channel_difference No pts for channel {}", update.channel_id);
+            return Ok(());
+        };
+        loop {
+            let request = tl::functions::updates::GetChannelDifference {
+                force: false,
+                channel: tl::types::InputChannel {
+                    channel_id: update.channel_id,
+                    access_hash,
+                }
+                .into(),
+                filter: tl::enums::ChannelMessagesFilter::Empty,
+                pts,
+                limit: CHANNEL_DIFFERENCE_LIMIT,
+            };
+            let done = match scheduled!(self, "updates.getChannelDifference", self.client().invoke(&request))? {
+                tl::enums::updates::ChannelDifference::Empty(d) => {
+                    pts = d.pts;
+                    d.r#final
+                }
+                tl::enums::updates::ChannelDifference::Difference(d) => {
+                    difference.messages.extend(d.new_messages);
+                    difference.updates.extend(d.other_updates);
+                    difference.users.extend(d.users);
+                    difference.chats.extend(d.chats);
+                    pts = d.pts;
+                    d.r#final
+                }
+                tl::enums::updates::ChannelDifference::TooLong(d) => {
+                    if let tl::enums::Dialog::Dialog(dialog) = d.dialog {
+                        if let Some(dialog_pts) = dialog.pts {
+                            difference.channel_states.push((update.channel_id, dialog_pts));
+                        }
+                    }
+                    difference.too_long = true;
+                    return Ok(());
+                }
+            };
+            if done {
+                difference.channel_states.push((update.channel_id, pts));
+                return Ok(());
+            }
+        }
+    }
+}
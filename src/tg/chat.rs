@@ -1,5 +1,6 @@
-use crate::tg::types::{ChatType, NativeChat, NativePackedChat, NativeRawMessage, NativeSeenChat};
+use crate::tg::types::{ChatType, NativeChat, NativeDraft, NativePackedChat, NativePeerNotifySettings, NativeRawMessage, NativeSeenChat};
 use crate::tg::utils::{get_profile_photo_path_and_count, ProfilePhotoPath};
+use crate::tg::scheduler::scheduled;
 use crate::tg::Backend;
 use anyhow::Result;
 use dashmap::{DashMap as HashMap, DashSet as HashSet};
@@ -14,7 +15,7 @@ use napi_ohos::tokio;
 use ohos_hilog_binding::debug;
 impl Backend {
     // pub async fn load_profile_photos(&mut self) -> Result<()> {
-    //     let mut dialog_iter = self.client.iter_dialogs();
+    //     let mut dialog_iter = self.client().iter_dialogs();
     //     while let Some(dialog) = dialog_iter.next().await? {
     //         let raw_chat = dialog.chat();
     //         let chat = NativeChat::from_raw(raw_chat).await;
@@ -40,14 +41,13 @@ impl Backend {
         &'static mut self,
         last_message_ids: Option<HashMap<i64, i32>>,
     ) -> Result<()> {
-        let mut dialog_iter = self.client.iter_dialogs();
-        while let Some(dialog) = dialog_iter.next().await? {
-            tokio::spawn(self.save_session());
+        let mut dialog_iter = self.client().iter_dialogs();
+        while let Some(dialog) = scheduled!(self, "messages.getDialogs", dialog_iter.next())? {
+            self.request_session_save();
             // let dialog = Box::leak(Box::new(dialog));
             let raw_chat = dialog.chat();
             let mut chat = NativeChat::from_raw(raw_chat).await;
             debug!("Loading chat:{}, chat_type: {:?}, forum: {}", chat.name, chat.chat_type, chat.forum);
-            let packed_chat = raw_chat.pack();
             // let profile_photo_path = Box::leak(Box::new(get_profile_photo_path_and_count(raw_chat.id())?));
             // if profile_photo_path.current.is_none() {
             //     let packed_chat = Box::leak(Box::new(packed_chat));
@@ -56,12 +56,16 @@ impl Backend {
             //     );
             // }
             chat.pinned = dialog.raw.pinned();
+            chat.notify_settings = NativePeerNotifySettings::from_dialog(&dialog.raw);
+            self.set_chat_notify_settings(chat.chat_id, chat.notify_settings.clone());
+            chat.draft = NativeDraft::from_dialog(&dialog.raw);
+            self.set_chat_draft(chat.chat_id, chat.draft.clone());
             let last_message_id = if let Some(last_message_ids) = last_message_ids.as_ref() {
                 last_message_ids.get(&chat.chat_id).map(|id| *id)
             } else {
                 None
             };
-            let message_iter = self.client.iter_messages(raw_chat);
+            let message_iter = self.client().iter_messages(raw_chat);
             debug!("Loading chat: {} after {:?}", chat.name, last_message_id);
             let sorted_messages =
                 self.load_messages_from_iter(message_iter, last_message_id).await?;
@@ -84,13 +88,8 @@ impl Backend {
             chat.last_message_sender_name = last_message.sender_name.clone();
             chat.last_message_text = last_message.text.clone();
             chat.last_message_timestamp = last_message.timestamp;
-            self.seen_packed_chats_map.insert(packed_chat.id, packed_chat);
-            let native_seen_chat = NativeSeenChat::from_raw(raw_chat);
-            self.cache_seen_chat_callback
-                .as_ref()
-                .unwrap()
-                .call(Ok(native_seen_chat.clone()), ThreadsafeFunctionCallMode::NonBlocking);
-            self.chats_map.insert(raw_chat.id(), chat.clone());
+            let native_seen_chat = self.cache_seen_chat(raw_chat);
+            self.cache_chat(&chat);
             debug!("before update_chat_callback call: chat name: {}", chat.name);
             self.update_chat_callback.as_ref().unwrap().call(
                 Ok((
@@ -101,6 +100,8 @@ impl Backend {
                 ThreadsafeFunctionCallMode::NonBlocking,
             );
         }
+        self.save_search_index().await;
+        self.save_store();
         debug!("load_chats_with_offset done!");
         Ok(())
     }
@@ -117,7 +118,7 @@ impl Backend {
             self.seen_packed_chats_map.insert(packed_chat.chat_id, PackedChat::from_hex(packed_chat.packed_chat.as_str())?);
         }
         for chat in chats.iter() {
-            self.chats_map.insert(chat.chat_id, chat.clone());
+            self.cache_chat(chat);
         }
         Ok(())
     }
@@ -137,7 +138,7 @@ impl Backend {
     //             hash: 0,
     //             add_offset: 0,
     //         };
-    //         let (messages, users, chats, rate) = match self.client.invoke(&request).await? {
+    //         let (messages, users, chats, rate) = match self.client().invoke(&request).await? {
     //             Messages::Messages(m) => {
     //                 total = m.messages.len();
     //                 (m.messages, m.users, m.chats, None)
@@ -204,7 +205,19 @@ impl Backend {
                     // let _permit = self.global_semaphore.acquire().await?;
                     // debug!("download_chat_photo acquired global_semaphore");
                     debug!("download_chat_photo Downloading profile photo for chat {} at {:?}", chat.name(), profile_photo_path.current);
-                    self.client.download_media(&profile_photo, &profile_photo_path.next).await?;
+                    let downloaded = self
+                        .cancellable(async {
+                            scheduled!(
+                                self,
+                                "upload.getFile",
+                                self.client().download_media(&profile_photo, &profile_photo_path.next)
+                            )
+                        })
+                        .await;
+                    if let Err(e) = downloaded {
+                        self.profile_photo_downloading_set.remove(&chat.id());
+                        return Err(e);
+                    }
                 }
 
                 self.profile_photo_downloading_set.remove(&chat.id());
@@ -242,7 +255,8 @@ impl Backend {
             // let _permit = self.global_semaphore.acquire().await?;
             // debug!("download_chat_photo_by_chat_id acquired global_semaphore");
             debug!("download_chat_photo_by_chat_id unpacking chat for chat {}", chat_id);
-            self.client.unpack_chat(*chat.unwrap()).await?
+            let packed_chat = *chat.unwrap();
+            scheduled!(self, "users.getUsers", self.client().unpack_chat(packed_chat))?
         };
         debug!("download_chat_photo_by_chat_id unpacked chat got: {:?}", chat);
         self.download_chat_photo(&chat, big, &profile_photo_path).await?;
@@ -254,7 +268,8 @@ impl Backend {
         let packed_chat = self.seen_packed_chats_map.get(&chat_id);
         match packed_chat {
             Some(packed_chat) => {
-                let chat = self.client.unpack_chat(*packed_chat).await?;
+                let packed_chat = *packed_chat;
+                let chat = scheduled!(self, "users.getUsers", self.client().unpack_chat(packed_chat))?;
 
                 match chat {
                     Chat::User(user) => { Ok(user.photo().map(|photo| photo.stripped_thumb.clone()).unwrap_or(None)) }
@@ -0,0 +1,74 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::NativeDraft;
+use crate::tg::utils::peer_id;
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use log::debug;
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use std::sync::atomic::Ordering;
+
+impl Backend {
+    /// Save the draft of the chat to the cloud, replacing the previous one.
+    pub async fn save_draft(&self, chat_id: i64, text: String, reply_to: Option<i32>) -> Result<()> {
+        let request = tl::functions::messages::SaveDraft {
+            no_webpage: false,
+            invert_media: false,
+            reply_to: reply_to.map(|msg_id| {
+                tl::types::InputReplyToMessage {
+                    reply_to_msg_id: msg_id,
+                    top_msg_id: None,
+                    reply_to_peer_id: None,
+                    quote_text: None,
+                    quote_entities: None,
+                    quote_offset: None,
+                }
+                .into()
+            }),
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            message: text.clone(),
+            entities: None,
+            media: None,
+            effect: None,
+        };
+        scheduled!(self, "messages.saveDraft", self.client().invoke(&request))?;
+        let draft = (!text.is_empty() || reply_to.is_some()).then(|| NativeDraft {
+            text,
+            date: chrono::Utc::now().timestamp(),
+            reply_to_message_id: reply_to,
+        });
+        self.set_chat_draft(chat_id, draft);
+        Ok(())
+    }
+
+    /// Saving an empty draft is how the server clears it.
+    pub async fn clear_draft(&self, chat_id: i64) -> Result<()> {
+        self.save_draft(chat_id, String::new(), None).await
+    }
+
+    pub(crate) fn set_chat_draft(&self, chat_id: i64, draft: Option<NativeDraft>) {
+        let Some(mut chat) = self.chats_map.get_mut(&chat_id) else {
+            return;
+        };
+        if chat.draft == draft {
+            return;
+        }
+        chat.draft = draft.clone();
+        drop(chat);
+        self.store_dirty.store(true, Ordering::Release);
+        if let Some(cb) = self.chat_draft_callback.as_ref() {
+            cb.call(Ok((chat_id, draft)), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+
+    /// Apply a draft saved (or cleared) on another device.
+    pub(crate) fn draft_update_handler(&self, update: &tl::types::UpdateDraftMessage) {
+        // drafts of forum topics are not tracked
+        if update.top_msg_id.is_some() {
+            return;
+        }
+        let chat_id = peer_id(&update.peer);
+        debug!("draft_update_handler Draft of {chat_id} changed");
+        self.set_chat_draft(chat_id, NativeDraft::from_raw(&update.draft));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{NativeInlineResult, NativeInlineResults, NativeMessage};
+use crate::tg::utils::random_id;
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use grammers_session::{PackedChat, PackedType};
+use log::debug;
+
+impl Backend {
+    /// Find the bot `@username` in the peers we have seen, resolving it only if needed:
+    /// contacts.resolveUsername is heavily rate limited.
+    pub(crate) async fn resolve_bot(&self, username: &str) -> Result<PackedChat> {
+        let username = username.trim_start_matches('@');
+        let seen = self.seen_chats_map.iter().find_map(|seen_chat| {
+            seen_chat
+                .username
+                .as_deref()
+                .is_some_and(|u| u.eq_ignore_ascii_case(username))
+                .then_some(seen_chat.chat_id)
+        });
+        if let Some(packed_chat) = seen.and_then(|chat_id| self.packed_chat(chat_id).ok()) {
+            return Ok(packed_chat);
+        }
+        debug!("resolve_bot Resolving @{username}");
+        let chat = scheduled!(self, "contacts.resolveUsername", self.client().resolve_username(username))?
+            .ok_or_else(|| anyhow::anyhow!("No one is called @{username}"))?;
+        self.cache_seen_chat(&chat);
+        Ok(chat.pack())
+    }
+
+    /// Ask the bot `@bot_username` for the results of `query` typed in `chat_id`.
+    pub async fn inline_query(
+        &self,
+        bot_username: String,
+        chat_id: i64,
+        query: String,
+        offset: Option<String>,
+    ) -> Result<NativeInlineResults> {
+        let bot = self.resolve_bot(&bot_username).await?;
+        if !matches!(bot.ty, PackedType::Bot) {
+            return Err(anyhow::anyhow!("@{} is not a bot", bot_username.trim_start_matches('@')));
+        }
+        let request = tl::functions::messages::GetInlineBotResults {
+            bot: tl::types::InputUser {
+                user_id: bot.id,
+                access_hash: bot.access_hash.unwrap_or(0),
+            }
+            .into(),
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            geo_point: None,
+            query,
+            offset: offset.unwrap_or_default(),
+        };
+        let tl::enums::messages::BotResults::Results(results) =
+            scheduled!(self, "messages.getInlineBotResults", self.client().invoke(&request))?;
+        Ok(NativeInlineResults {
+            query_id: results.query_id,
+            next_offset: results.next_offset.filter(|offset| !offset.is_empty()),
+            gallery: results.gallery,
+            cache_time: results.cache_time,
+            results: results.results.iter().map(NativeInlineResult::from_raw).collect(),
+        })
+    }
+
+    /// Send the result `result_id` of the inline query `query_id` to `chat_id`.
+    pub async fn send_inline_result(&self, chat_id: i64, query_id: i64, result_id: String) -> Result<Vec<NativeMessage>> {
+        let packed_chat = self.packed_chat(chat_id)?;
+        let request = tl::functions::messages::SendInlineBotResult {
+            silent: false,
+            background: false,
+            clear_draft: true,
+            hide_via: false,
+            peer: packed_chat.to_input_peer(),
+            reply_to: None,
+            random_id: random_id()?,
+            query_id,
+            id: result_id,
+            schedule_date: None,
+            send_as: None,
+            quick_reply_shortcut: None,
+        };
+        let updates = scheduled!(self, "messages.sendInlineBotResult", self.client().invoke(&request))?;
+        self.messages_from_updates(packed_chat, updates).await
+    }
+}
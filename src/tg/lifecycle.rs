@@ -0,0 +1,140 @@
+use crate::tg::session::load_session;
+use crate::tg::types::LifecycleState;
+use crate::tg::Backend;
+use anyhow::Result;
+use log::{debug, error, info};
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use napi_ohos::tokio;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// How long the update loop gets to finish the update it is handling before it is
+/// aborted.
+const UPDATE_LOOP_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Backend {
+    /// Reconnect with the saved session through `proxy_url`, and switch to the new client
+    /// and the `bridge` serving it. The caller holds `lifecycle_mutex` and restarts the
+    /// update loop if needed. Requests in flight keep their clone of the old client.
+    pub(crate) async fn reconnect_client(
+        &self,
+        proxy_url: Option<String>,
+        bridge: Option<tokio::task::JoinHandle<()>>,
+    ) -> Result<()> {
+        let client = Backend::connect_client(load_session()?, proxy_url).await?;
+        *self.client.write().unwrap() = client;
+        if let Some(old_bridge) = std::mem::replace(&mut *self.proxy_bridge.lock().unwrap(), bridge) {
+            old_bridge.abort();
+        }
+        Ok(())
+    }
+
+    pub fn get_lifecycle_state(&self) -> LifecycleState {
+        *self.lifecycle_state.lock().unwrap()
+    }
+
+    fn set_lifecycle_state(&self, state: LifecycleState) {
+        let previous = std::mem::replace(&mut *self.lifecycle_state.lock().unwrap(), state);
+        if previous == state {
+            return;
+        }
+        info!("Lifecycle state: {:?} -> {:?}", previous, state);
+        if let Some(cb) = self.lifecycle_state_callback.as_ref() {
+            cb.call(Ok(state), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+
+    pub(crate) fn is_update_loop_running(&self) -> bool {
+        self.run_handler
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|handler| !handler.is_finished())
+    }
+
+    pub(crate) fn start_update_loop(&'static self) {
+        let token = CancellationToken::new();
+        *self.run_token.lock().unwrap() = token.clone();
+        let handler = tokio::spawn(self.run(token));
+        self.run_handler.lock().unwrap().replace(handler);
+    }
+
+    /// Let the update loop finish the update at hand and wait for it to exit.
+    pub(crate) async fn stop_update_loop(&self) {
+        self.run_token.lock().unwrap().cancel();
+        let handler = self.run_handler.lock().unwrap().take();
+        if let Some(mut handler) = handler {
+            match tokio::time::timeout(UPDATE_LOOP_DRAIN_TIMEOUT, &mut handler).await {
+                Ok(Ok(Ok(()))) => debug!("stop_update_loop Update loop drained"),
+                Ok(Ok(Err(e))) => error!("stop_update_loop Update loop exited with {e}"),
+                Ok(Err(e)) => error!("stop_update_loop Update loop panicked: {e}"),
+                Err(_) => {
+                    error!("stop_update_loop Update loop did not drain in time, aborting it");
+                    handler.abort();
+                }
+            }
+        }
+    }
+
+    /// Start handling updates and sending queued messages.
+    pub async fn start(&'static self) -> Result<()> {
+        let _guard = self.lifecycle_mutex.lock().await;
+        if self.get_lifecycle_state() != LifecycleState::Stopped || self.is_update_loop_running() {
+            return Err(anyhow::anyhow!("homo::tg::Backend is already {:?}", self.get_lifecycle_state()));
+        }
+        let outbox_handler = tokio::spawn(self.run_outbox(self.transfer_token.lock().unwrap().clone()));
+        self.outbox_handler.lock().unwrap().replace(outbox_handler);
+        self.start_update_loop();
+        self.set_lifecycle_state(LifecycleState::Running);
+        Ok(())
+    }
+
+    /// The app went to the background: stop handling updates and persist everything,
+    /// but leave the connection and the transfers alone.
+    pub async fn pause(&'static self) -> Result<()> {
+        let _guard = self.lifecycle_mutex.lock().await;
+        if self.get_lifecycle_state() != LifecycleState::Running {
+            return Err(anyhow::anyhow!("Cannot pause while {:?}", self.get_lifecycle_state()));
+        }
+        self.stop_update_loop().await;
+        self.flush().await;
+        self.set_lifecycle_state(LifecycleState::Paused);
+        Ok(())
+    }
+
+    /// The app came back to the foreground.
+    pub async fn resume(&'static self) -> Result<()> {
+        let _guard = self.lifecycle_mutex.lock().await;
+        if self.get_lifecycle_state() != LifecycleState::Paused {
+            return Err(anyhow::anyhow!("Cannot resume while {:?}", self.get_lifecycle_state()));
+        }
+        self.start_update_loop();
+        self.wake_outbox();
+        self.set_lifecycle_state(LifecycleState::Running);
+        Ok(())
+    }
+
+    /// Drain the update loop, cancel every transfer and persist the state. Calling it
+    /// again, or before [`Backend::start`], does nothing.
+    pub async fn shutdown(&'static self) {
+        let _guard = self.lifecycle_mutex.lock().await;
+        if self.get_lifecycle_state() == LifecycleState::Stopped {
+            debug!("shutdown Already stopped");
+            return;
+        }
+        self.set_lifecycle_state(LifecycleState::ShuttingDown);
+        self.stop_update_loop().await;
+        self.stop_live_locations().await;
+        let transfer_token = std::mem::replace(&mut *self.transfer_token.lock().unwrap(), CancellationToken::new());
+        transfer_token.cancel();
+        // wait for the outbox worker, so that `start` never runs two of them
+        let outbox_handler = self.outbox_handler.lock().unwrap().take();
+        if let Some(outbox_handler) = outbox_handler {
+            if let Err(e) = outbox_handler.await {
+                error!("shutdown Outbox worker panicked: {e}");
+            }
+        }
+        self.flush().await;
+        self.set_lifecycle_state(LifecycleState::Stopped);
+    }
+}
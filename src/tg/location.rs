@@ -0,0 +1,153 @@
+use crate::tg::types::{NativeContact, NativeLocation, NativeMessage, NativeVenue};
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use log::{debug, error, info};
+use napi_ohos::tokio;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Live location edits are rate limited, positions reported faster than this are
+/// coalesced into the next edit.
+const LIVE_LOCATION_EDIT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A live location being shared: the latest position the app reported and whether it
+/// still has to be sent.
+pub(crate) struct LiveLocation {
+    pending: Option<NativeLocation>,
+    token: CancellationToken,
+}
+
+impl Backend {
+    pub async fn send_location(&self, chat_id: i64, location: NativeLocation) -> Result<Vec<NativeMessage>> {
+        let media = match location.venue.as_ref() {
+            Some(venue) => self.venue_media(&location, venue),
+            None => tl::types::InputMediaGeoPoint {
+                geo_point: location.to_input_point(),
+            }
+            .into(),
+        };
+        self.send_media_raw(chat_id, media, String::new()).await
+    }
+
+    fn venue_media(&self, location: &NativeLocation, venue: &NativeVenue) -> tl::enums::InputMedia {
+        tl::types::InputMediaVenue {
+            geo_point: location.to_input_point(),
+            title: venue.title.clone(),
+            address: venue.address.clone(),
+            provider: venue.provider.clone(),
+            venue_id: venue.venue_id.clone(),
+            venue_type: venue.venue_type.clone(),
+        }
+        .into()
+    }
+
+    pub async fn send_contact(&self, chat_id: i64, contact: NativeContact) -> Result<Vec<NativeMessage>> {
+        let media = tl::types::InputMediaContact {
+            phone_number: contact.phone_number,
+            first_name: contact.first_name,
+            last_name: contact.last_name,
+            vcard: contact.vcard,
+        };
+        self.send_media_raw(chat_id, media.into(), String::new()).await
+    }
+
+    /// Roll a dice, the server decides the value.
+    pub async fn send_dice(&self, chat_id: i64, emoticon: String) -> Result<Vec<NativeMessage>> {
+        let media = tl::types::InputMediaDice { emoticon };
+        self.send_media_raw(chat_id, media.into(), String::new()).await
+    }
+
+    /// Start sharing the live location for `period` seconds. The app keeps reporting
+    /// positions with [`Backend::update_live_location`] until it expires or is stopped.
+    pub async fn send_live_location(
+        &'static self,
+        chat_id: i64,
+        location: NativeLocation,
+        period: i32,
+    ) -> Result<Vec<NativeMessage>> {
+        let media = tl::types::InputMediaGeoLive {
+            stopped: false,
+            geo_point: location.to_input_point(),
+            heading: location.heading,
+            period: Some(period),
+            proximity_notification_radius: location.proximity_notification_radius,
+        };
+        let messages = self.send_media_raw(chat_id, media.into(), String::new()).await?;
+        if let Some(message) = messages.first() {
+            let key = (chat_id, message.message_id);
+            // not a child of the transfer token: shutdown has to stop it on the server first
+            let token = CancellationToken::new();
+            self.live_locations.insert(key, LiveLocation { pending: None, token: token.clone() });
+            tokio::spawn(self.run_live_location(key, token, Duration::from_secs(period.max(0) as u64)));
+        }
+        Ok(messages)
+    }
+
+    /// Report a new position for the live location of `msg_id`.
+    pub fn update_live_location(&self, chat_id: i64, msg_id: i32, location: NativeLocation) -> Result<()> {
+        let mut live = self
+            .live_locations
+            .get_mut(&(chat_id, msg_id))
+            .ok_or_else(|| anyhow::anyhow!("Message {msg_id} is not a live location being shared"))?;
+        live.pending = Some(location);
+        Ok(())
+    }
+
+    pub async fn stop_live_location(&self, chat_id: i64, msg_id: i32) -> Result<Vec<NativeMessage>> {
+        if let Some((_, live)) = self.live_locations.remove(&(chat_id, msg_id)) {
+            live.token.cancel();
+        }
+        let media = tl::types::InputMediaGeoLive {
+            stopped: true,
+            geo_point: tl::enums::InputGeoPoint::Empty,
+            heading: None,
+            period: None,
+            proximity_notification_radius: None,
+        };
+        self.edit_message_media(chat_id, msg_id, media.into()).await
+    }
+
+    /// Stop sharing every live location, so that none outlives the app on the server.
+    pub(crate) async fn stop_live_locations(&self) {
+        let keys: Vec<(i64, i32)> = self.live_locations.iter().map(|live| *live.key()).collect();
+        for (chat_id, msg_id) in keys {
+            info!("stop_live_locations Stopping live location {chat_id}/{msg_id}");
+            if let Err(e) = self.stop_live_location(chat_id, msg_id).await {
+                error!("stop_live_locations Failed to stop {chat_id}/{msg_id}: {e}");
+            }
+        }
+    }
+
+    /// Push the latest reported position every [`LIVE_LOCATION_EDIT_INTERVAL`] until the
+    /// live location expires or gets stopped.
+    async fn run_live_location(&'static self, key: (i64, i32), token: CancellationToken, period: Duration) {
+        let (chat_id, msg_id) = key;
+        let expires_at = tokio::time::Instant::now() + period;
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = tokio::time::sleep_until(expires_at) => {
+                    info!("run_live_location Live location {chat_id}/{msg_id} expired");
+                    break;
+                }
+                _ = tokio::time::sleep(LIVE_LOCATION_EDIT_INTERVAL) => {}
+            }
+            let Some(location) = self.live_locations.get_mut(&key).and_then(|mut live| live.pending.take()) else {
+                continue;
+            };
+            debug!("run_live_location Moving {chat_id}/{msg_id} to {}, {}", location.latitude, location.longitude);
+            let media = tl::types::InputMediaGeoLive {
+                stopped: false,
+                geo_point: location.to_input_point(),
+                heading: location.heading,
+                period: None,
+                proximity_notification_radius: location.proximity_notification_radius,
+            };
+            if let Err(e) = self.edit_message_media(chat_id, msg_id, media.into()).await {
+                error!("run_live_location Failed to update {chat_id}/{msg_id}: {e}");
+            }
+        }
+        self.live_locations.remove(&key);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::tg::types::LoginState;
+use crate::tg::scheduler::scheduled;
 use crate::tg::Backend;
 use anyhow::Result;
 use grammers_client::SignInError;
@@ -6,10 +7,10 @@ use log::{debug, error};
 
 impl Backend {
     pub async fn login_with_phone(&mut self, phone: String) -> Result<LoginState> {
-        if !self.is_logged_in().await {
+        if !self.is_logged_in().await? {
             debug!("Signing in...");
 
-            let login_token = self.client.request_login_code(&phone).await;
+            let login_token = scheduled!(self, "auth.sendCode", self.client().request_login_code(&phone));
             match login_token {
                 Ok(token) => {
                     self.login_token.replace(token);
@@ -32,11 +33,12 @@ impl Backend {
     }
 
     pub async fn provide_verify_code(&mut self, code: String) -> Result<LoginState> {
-        if !self.is_logged_in().await {
-            let signed_in = self
-                .client
-                .sign_in(self.login_token.as_ref().unwrap(), &code)
-                .await;
+        if !self.is_logged_in().await? {
+            let signed_in = scheduled!(
+                self,
+                "auth.signIn",
+                self.client().sign_in(self.login_token.as_ref().unwrap(), &code)
+            );
             match signed_in {
                 Err(SignInError::PasswordRequired(password_token)) => {
                     debug!("Password required");
@@ -74,11 +76,12 @@ impl Backend {
         if self.login_state.as_ref().unwrap() != &LoginState::PasswordRequired {
             return Err(anyhow::anyhow!("Password not required!"));
         }
-        if !self.is_logged_in().await {
-            let signed_in = self
-                .client
-                .check_password(self.password_token.clone().unwrap(), &password)
-                .await;
+        if !self.is_logged_in().await? {
+            let signed_in = scheduled!(
+                self,
+                "auth.checkPassword",
+                self.client().check_password(self.password_token.clone().unwrap(), &password)
+            );
             match signed_in {
                 Ok(user) => {
                     debug!("Signed in!");
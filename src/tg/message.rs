@@ -1,81 +1,88 @@
-use crate::tg::types::{MediaType, NativeChat, NativeMessage, NativeSeenChat, UpdateUploadProgressCallback};
-use crate::tg::utils::{get_download_dir, get_media_path, get_profile_photo_path_and_count};
+use crate::tg::types::{ChatActionKind, MediaType, NativeChat, NativeDocumentRef, NativeMessage, NativeSeenChat, NativeSendOptions, UpdateUploadProgressCallback};
+use crate::tg::webpage::link_preview_media;
+use crate::tg::utils::{get_download_dir, get_media_path, get_profile_photo_path_and_count, random_id};
+use crate::tg::scheduler::scheduled;
 use crate::tg::Backend;
 use anyhow::Result;
 use grammers_client::client::messages::MessageIter;
-use grammers_client::{grammers_tl_types as tl, InputMedia};
+use grammers_client::grammers_tl_types as tl;
 use grammers_tl_types::enums::InputMessage;
 use log::{debug, error};
 use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
 use napi_ohos::tokio;
 use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use grammers_client::types::Message;
+use grammers_client::types::{Chat, Message};
+use grammers_session::PackedChat;
 
 impl Backend {
-    pub(crate) async fn incoming_message_handler(&'static self, raw_message: &Message) {
-        self.seen_packed_chats_map.insert(raw_message.chat().id(), raw_message.chat().pack());
-        self.cache_seen_chat_callback
-            .as_ref()
-            .unwrap()
-            .call(Ok(NativeSeenChat::from_raw(&raw_message.chat())), ThreadsafeFunctionCallMode::NonBlocking);
+    /// Handle a new message. `live` is false for the backlog received while catching up,
+    /// for which the app should not notify and we don't bother fetching avatars.
+    pub(crate) async fn incoming_message_handler(&'static self, raw_message: &Message, live: bool) {
+        self.cache_seen_chat(&raw_message.chat());
 
         if let Some(sender) = raw_message.sender() {
-            self.seen_packed_chats_map.insert(sender.id(), sender.pack());
-            self.cache_seen_chat_callback
-                .as_ref()
-                .unwrap()
-                .call(Ok(NativeSeenChat::from_raw(&sender)), ThreadsafeFunctionCallMode::NonBlocking);
+            self.cache_seen_chat(&sender);
+            self.stop_typing(raw_message.chat().id(), sender.id());
         }
 
-        tokio::spawn(self.download_sender_chat_photo(raw_message.sender()));
-        let old_chat = self.chats_map.get_mut(&raw_message.chat().id());
-        match old_chat {
-            Some(mut old_chat) => {
-                debug!("tg::Backend::run() New message in chat {}", old_chat.name);
-                let message = NativeMessage::from_raw(&raw_message);
-                debug!("tg::Backend::run() message: {:?}", message);
-                old_chat.last_message_sender_name = raw_message.sender().map(|s| s.name().to_string()).unwrap_or("".to_string());
-                let raw_message = &raw_message.raw;
-                old_chat.last_message_id = raw_message.id;
-                old_chat.last_message_text = raw_message.message.clone();
-                old_chat.last_message_timestamp = raw_message.date as i64;
-                debug!("tg::Backend::run() old_chat: {:?}", old_chat);
-                drop(old_chat);
-                self.incoming_message_callback
-                    .as_ref()
-                    .unwrap()
-                    .call(Ok((None, message)), ThreadsafeFunctionCallMode::NonBlocking);
-            }
-            None => {
-                drop(old_chat);
-                let raw_chat = raw_message.chat();
-                tokio::spawn(self.download_sender_chat_photo(raw_message.sender()));
-                debug!(
-                                "New message in unknown chat {}: {}",
-                                raw_chat.name(),
-                                raw_message.text()
-                            );
-                // let chat = Chat::from_raw(raw_chat.clone()).await;
-                let message = NativeMessage::from_raw(&raw_message);
-                // let raw_message = raw_message.raw;
-                let mut chat = NativeChat::from_raw(&raw_chat).await; // TODO: we current assume that this chat is not possibly a pinned chat
-                chat.last_message_id = message.message_id;
-                chat.last_message_sender_name = message.sender_name.clone();
-                chat.last_message_text = message.text.clone();
-                chat.last_message_timestamp = message.timestamp;
-                debug!("Chat: {:?}", chat);
-                debug!("Message: {:?}", message);
-                self.chats_map.insert(raw_chat.id(), chat.clone());
-                debug!("chats_map updated!");
-                self.incoming_message_callback.as_ref()
-                    .expect("incoming_message_callback is None")
-                    .call(Ok((Some(chat), message)), ThreadsafeFunctionCallMode::NonBlocking);
-            }
-        };
+        if live {
+            tokio::spawn(self.download_sender_chat_photo(raw_message.sender()));
+        }
+        let message = NativeMessage::from_raw(&raw_message);
+        self.search_index.index_message(&message);
+        self.cache_recent_message(&message);
+        if let Some(message) = self.deliver_message_to_known_chat(message, live) {
+            let raw_chat = raw_message.chat();
+            debug!(
+                "New message in unknown chat {}: {}",
+                raw_chat.name(),
+                raw_message.text()
+            );
+            self.deliver_message_to_new_chat(&raw_chat, message, live).await;
+        }
         debug!("Incoming message callback called!");
     }
 
+    /// Make `message` the last one of its chat and report it, if we have the chat.
+    /// Gives the message back otherwise.
+    pub(crate) fn deliver_message_to_known_chat(&self, message: NativeMessage, live: bool) -> Option<NativeMessage> {
+        let Some(mut old_chat) = self.chats_map.get_mut(&message.chat_id) else {
+            return Some(message);
+        };
+        debug!("tg::Backend::run() New message in chat {}", old_chat.name);
+        debug!("tg::Backend::run() message: {:?}", message);
+        old_chat.last_message_sender_name = message.sender_name.clone();
+        old_chat.last_message_id = message.message_id;
+        old_chat.last_message_text = message.text.clone();
+        old_chat.last_message_timestamp = message.timestamp;
+        debug!("tg::Backend::run() old_chat: {:?}", old_chat);
+        drop(old_chat);
+        self.store_dirty.store(true, Ordering::Release);
+        self.incoming_message_callback
+            .as_ref()
+            .unwrap()
+            .call(Ok((None, message, live)), ThreadsafeFunctionCallMode::NonBlocking);
+        None
+    }
+
+    /// Create the chat `message` is the first we hear of, and report both.
+    pub(crate) async fn deliver_message_to_new_chat(&self, raw_chat: &Chat, message: NativeMessage, live: bool) {
+        let mut chat = NativeChat::from_raw(raw_chat).await; // TODO: we current assume that this chat is not possibly a pinned chat
+        chat.last_message_id = message.message_id;
+        chat.last_message_sender_name = message.sender_name.clone();
+        chat.last_message_text = message.text.clone();
+        chat.last_message_timestamp = message.timestamp;
+        debug!("Chat: {:?}", chat);
+        debug!("Message: {:?}", message);
+        self.cache_chat(&chat);
+        debug!("chats_map updated!");
+        self.incoming_message_callback.as_ref()
+            .expect("incoming_message_callback is None")
+            .call(Ok((Some(chat), message, live)), ThreadsafeFunctionCallMode::NonBlocking);
+    }
+
     pub(crate) async fn load_messages_from_iter(
         &'static self,
         message_iter: MessageIter,
@@ -86,7 +93,7 @@ impl Backend {
         let mut message_iter = message_iter.limit(100);
         let now = chrono::Utc::now().timestamp();
         message_iter = message_iter.max_date(now as i32); // TODO: What does this do?
-        while let Some(raw_message) = message_iter.next().await? {
+        while let Some(raw_message) = scheduled!(self, "messages.getHistory", message_iter.next())? {
             if let Some(last_message_id) = last_message_id {
                 if raw_message.id() <= last_message_id {
                     break;
@@ -95,13 +102,11 @@ impl Backend {
             let sender = raw_message.sender();
             // tokio::spawn(self.download_sender_chat_photo(sender.clone()));
             if let Some(sender) = sender {
-                self.seen_packed_chats_map.insert(sender.id(), sender.pack());
-                self.cache_seen_chat_callback
-                    .as_ref()
-                    .unwrap()
-                    .call(Ok(NativeSeenChat::from_raw(&sender)), ThreadsafeFunctionCallMode::NonBlocking);
+                self.cache_seen_chat(&sender);
             }
             let message = NativeMessage::from_raw(&raw_message);
+            self.search_index.index_message(&message);
+            self.cache_recent_message(&message);
             sorted_messages.insert(message.message_id, message);
         }
         Ok(sorted_messages)
@@ -112,79 +117,302 @@ impl Backend {
         chat: &grammers_client::types::Chat,
     ) -> Result<Vec<NativeMessage>> {
         let mut sorted_messages: Vec<NativeMessage> = Vec::new();
-        let mut messages = self.client.iter_messages(chat).limit(5);
-        while let Some(message) = messages.next().await? {
-            sorted_messages.push(NativeMessage::from_raw(&message));
+        let mut messages = self.client().iter_messages(chat).limit(5);
+        while let Some(message) = scheduled!(self, "messages.getHistory", messages.next())? {
+            let message = NativeMessage::from_raw(&message);
+            self.search_index.index_message(&message);
+            sorted_messages.push(message);
         }
         sorted_messages.reverse();
         Ok(sorted_messages)
     }
 
-    pub async fn send_message(&self, chat_id: i64, text: String, medias: Option<Vec<String>>,
-                              update_upload_progress_callback: Arc<UpdateUploadProgressCallback>) -> Result<Vec<NativeMessage>> {
+    /// Send a text message, or an album of photos with `text` as its caption, as `options`
+    /// say. Every message sent from the composer goes through here.
+    pub async fn send_message(
+        &self,
+        chat_id: i64,
+        text: String,
+        medias: Option<Vec<String>>,
+        options: NativeSendOptions,
+        update_upload_progress_callback: Option<Arc<UpdateUploadProgressCallback>>,
+    ) -> Result<Vec<NativeMessage>> {
         debug!("Sending message to chat {}: {}", chat_id, text);
-        // let chats_map = self.chats_map.read().await;
-        let packed_chat = self.seen_packed_chats_map.get(&chat_id).unwrap_or_else(|| {
-            error!("Chat with id {} not found in chats_map!", chat_id);
-            panic!("Chat with id {} not found in chats_map!", chat_id)
-        }).clone();
-        use grammers_client::InputMessage;
-        let mut album = Vec::new();
-
-        if let Some(medias) = medias {
-            debug!("Sending media message with {} messages and text {}", medias.len(), text);
-            for (index, media) in medias.iter().enumerate() {
-                let raw_file = std::fs::read(media)?;
-                let len = raw_file.len();
-                // use std::io::Cursor to keep track of the progress
-                let mut stream = std::io::Cursor::new(raw_file);
-                let stream_leaked = Box::leak(Box::new(stream.clone()));
-                let callback_ref = Box::leak(Box::new(update_upload_progress_callback.clone()));
-                let _handler = tokio::spawn(async move {
-                    while stream_leaked.position() < (len - 1) as u64 {
-                        let progress = (stream_leaked.position() as f64 / len as f64) * 100f64;
-                        callback_ref.call(Ok((index as i64, progress as i64)), ThreadsafeFunctionCallMode::NonBlocking);
-                        // sleep for short time to avoid high CPU usage
-                    }
-                });
-                let file_name = std::path::Path::new(media).file_name().unwrap().to_str().unwrap().split("/").last().unwrap();
-                let uploaded_file = self.client.upload_stream(&mut stream, len, file_name.to_string()).await;
-                match uploaded_file {
-                    Ok(file) => {
-                        let input_media = if index == 0 {
-                            InputMedia::caption(text.clone())
-                        } else {
-                            InputMedia::caption("")
-                        }.photo(file);
-                        album.push(input_media);
-                    }
-                    Err(e) => {
-                        error!("Failed to upload media: {e}");
-                        return Err(anyhow::Error::from(e));
+        let packed_chat = self.packed_chat(chat_id)?;
+        let medias = medias.unwrap_or_default();
+        // checked before uploading anything
+        let schedule_date = options.schedule_date()?;
+        debug!("send_message {} medias, {:?}", medias.len(), options);
+        if medias.is_empty() {
+            if let Some(media) = link_preview_media(&text, &options)? {
+                return self.send_media_with_options(chat_id, media, text, &options).await;
+            }
+            let request = tl::functions::messages::SendMessage {
+                no_webpage: options.no_webpage(),
+                silent: options.silent(),
+                background: false,
+                clear_draft: true,
+                noforwards: false,
+                update_stickersets_order: false,
+                invert_media: options.invert_media(),
+                peer: packed_chat.to_input_peer(),
+                reply_to: None,
+                message: text,
+                random_id: random_id()?,
+                reply_markup: None,
+                entities: None,
+                schedule_date,
+                send_as: None,
+                quick_reply_shortcut: None,
+                effect: None,
+            };
+            let updates = scheduled!(self, "messages.sendMessage", self.client().invoke(&request))?;
+            self.set_chat_draft(chat_id, None);
+            return self.messages_from_updates(packed_chat, updates).await;
+        }
+        let mut uploaded = Vec::with_capacity(medias.len());
+        for (index, media) in medias.iter().enumerate() {
+            let file = self
+                .upload_file(media, index, update_upload_progress_callback.clone(), Some((chat_id, ChatActionKind::UploadPhoto)))
+                .await?;
+            uploaded.push(tl::types::InputMediaUploadedPhoto {
+                spoiler: false,
+                file: file.raw,
+                stickers: None,
+                ttl_seconds: None,
+            });
+        }
+        if uploaded.len() == 1 {
+            let media = uploaded.pop().unwrap();
+            return self.send_media_with_options(chat_id, media.into(), text, &options).await;
+        }
+        // the photos of an album have to be on the server before they are grouped
+        let mut multi_media = Vec::with_capacity(uploaded.len());
+        for (index, media) in uploaded.into_iter().enumerate() {
+            let request = tl::functions::messages::UploadMedia {
+                business_connection_id: None,
+                peer: packed_chat.to_input_peer(),
+                media: media.into(),
+            };
+            let photo = match scheduled!(self, "messages.uploadMedia", self.client().invoke(&request))? {
+                tl::enums::MessageMedia::Photo(tl::types::MessageMediaPhoto {
+                    photo: Some(tl::enums::Photo::Photo(photo)),
+                    ..
+                }) => photo,
+                other => return Err(anyhow::anyhow!("Unexpected uploaded media: {other:?}")),
+            };
+            multi_media.push(
+                tl::types::InputSingleMedia {
+                    media: tl::types::InputMediaPhoto {
+                        spoiler: false,
+                        id: tl::types::InputPhoto {
+                            id: photo.id,
+                            access_hash: photo.access_hash,
+                            file_reference: photo.file_reference,
+                        }
+                        .into(),
+                        ttl_seconds: None,
                     }
+                    .into(),
+                    random_id: random_id()?,
+                    message: if index == 0 { text.clone() } else { String::new() },
+                    entities: None,
                 }
+                .into(),
+            );
+        }
+        let request = tl::functions::messages::SendMultiMedia {
+            silent: options.silent(),
+            background: false,
+            clear_draft: true,
+            noforwards: false,
+            update_stickersets_order: false,
+            invert_media: options.invert_media(),
+            peer: packed_chat.to_input_peer(),
+            reply_to: None,
+            multi_media,
+            schedule_date,
+            send_as: None,
+            quick_reply_shortcut: None,
+            effect: None,
+        };
+        let updates = scheduled!(self, "messages.sendMultiMedia", self.client().invoke(&request))?;
+        self.set_chat_draft(chat_id, None);
+        self.messages_from_updates(packed_chat, updates).await
+    }
+
+    /// Send `media` to the chat with `text` as its caption, for the media kinds grammers
+    /// has no builder for.
+    pub(crate) async fn send_media_raw(
+        &self,
+        chat_id: i64,
+        media: tl::enums::InputMedia,
+        text: String,
+    ) -> Result<Vec<NativeMessage>> {
+        self.send_media_with_options(chat_id, media, text, &NativeSendOptions::default()).await
+    }
+
+    pub(crate) async fn send_media_with_options(
+        &self,
+        chat_id: i64,
+        media: tl::enums::InputMedia,
+        text: String,
+        options: &NativeSendOptions,
+    ) -> Result<Vec<NativeMessage>> {
+        let packed_chat = self.packed_chat(chat_id)?;
+        let schedule_date = options.schedule_date()?;
+        let request = tl::functions::messages::SendMedia {
+            silent: options.silent(),
+            background: false,
+            clear_draft: true,
+            noforwards: false,
+            update_stickersets_order: false,
+            invert_media: options.invert_media(),
+            peer: packed_chat.to_input_peer(),
+            reply_to: None,
+            media,
+            message: text,
+            random_id: random_id()?,
+            reply_markup: None,
+            entities: None,
+            schedule_date,
+            send_as: None,
+            quick_reply_shortcut: None,
+            effect: None,
+        };
+        let updates = scheduled!(self, "messages.sendMedia", self.client().invoke(&request))?;
+        // the server cleared the draft along with the message
+        self.set_chat_draft(chat_id, None);
+        self.messages_from_updates(packed_chat, updates).await
+    }
+
+    /// Upload the file at `path` and send it as a document with `attributes`.
+    pub(crate) async fn send_uploaded_document(
+        &self,
+        chat_id: i64,
+        path: &str,
+        mime_type: &str,
+        attributes: Vec<tl::enums::DocumentAttribute>,
+        nosound_video: bool,
+        caption: String,
+        update_upload_progress_callback: Option<Arc<UpdateUploadProgressCallback>>,
+    ) -> Result<Vec<NativeMessage>> {
+        let action = attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                tl::enums::DocumentAttribute::Audio(audio) if audio.voice => Some(ChatActionKind::UploadAudio),
+                tl::enums::DocumentAttribute::Video(video) if video.round_message => Some(ChatActionKind::UploadRound),
+                tl::enums::DocumentAttribute::Video(_) => Some(ChatActionKind::UploadVideo),
+                _ => None,
+            })
+            .unwrap_or(ChatActionKind::UploadDocument);
+        let uploaded = self
+            .upload_file(path, 0, update_upload_progress_callback, Some((chat_id, action)))
+            .await?;
+        let media = tl::types::InputMediaUploadedDocument {
+            nosound_video,
+            force_file: false,
+            spoiler: false,
+            file: uploaded.raw,
+            thumb: None,
+            mime_type: mime_type.to_string(),
+            attributes,
+            stickers: None,
+            ttl_seconds: None,
+        };
+        self.send_media_raw(chat_id, media.into(), caption).await
+    }
+
+    /// Send a document that is already on the server, e.g. a sticker or a saved GIF.
+    pub(crate) async fn send_document(
+        &self,
+        chat_id: i64,
+        document: &NativeDocumentRef,
+        caption: String,
+    ) -> Result<Vec<NativeMessage>> {
+        let media = tl::types::InputMediaDocument {
+            spoiler: false,
+            id: document.to_input(),
+            ttl_seconds: None,
+            query: None,
+        };
+        self.send_media_raw(chat_id, media.into(), caption).await
+    }
+
+    /// Replace the media of the message `msg_id`.
+    pub(crate) async fn edit_message_media(
+        &self,
+        chat_id: i64,
+        msg_id: i32,
+        media: tl::enums::InputMedia,
+    ) -> Result<Vec<NativeMessage>> {
+        let packed_chat = self.packed_chat(chat_id)?;
+        let request = tl::functions::messages::EditMessage {
+            no_webpage: false,
+            invert_media: false,
+            peer: packed_chat.to_input_peer(),
+            id: msg_id,
+            message: None,
+            media: Some(media),
+            reply_markup: None,
+            entities: None,
+            schedule_date: None,
+            quick_reply_shortcut_id: None,
+        };
+        let updates = scheduled!(self, "messages.editMessage", self.client().invoke(&request))?;
+        self.messages_from_updates(packed_chat, updates).await
+    }
+
+    /// Turn the `Updates` answered to a request sending (or editing) messages into those messages,
+    /// indexed and cached like the ones `send_message` returns. Scheduled messages are returned
+    /// as they are, they live apart from the history.
+    pub(crate) async fn messages_from_updates(
+        &self,
+        packed_chat: PackedChat,
+        updates: tl::enums::Updates,
+    ) -> Result<Vec<NativeMessage>> {
+        let updates = match updates {
+            tl::enums::Updates::Updates(updates) => updates.updates,
+            tl::enums::Updates::Combined(updates) => updates.updates,
+            tl::enums::Updates::UpdateShort(update) => vec![update.update],
+            tl::enums::Updates::UpdateShortSentMessage(sent) => {
+                vec![tl::types::UpdateMessageId { id: sent.id, random_id: 0 }.into()]
             }
-            let album_sent = self.client.send_album(packed_chat, album).await?;
-            debug!("Album sent: {:?}", album_sent);
-            Ok(album_sent.iter().map(|m| NativeMessage::from_raw(m.as_ref().unwrap())).collect())
-        } else {
-            debug!("Sending text message: {}", text);
-            let message_sent = self
-                .client
-                .send_message(packed_chat, InputMessage::text(text.clone()))
-                .await;
-            debug!("send_message returned: {:?}", message_sent);
-            match message_sent {
-                Err(e) => {
-                    error!("Failed to send message: {e}");
-                    Err(anyhow::Error::from(e))
-                }
-                Ok(message_sent) => {
-                    debug!("Message sent: {:?}", message_sent);
-                    Ok(vec![NativeMessage::from_raw(&message_sent)])
+            _ => Vec::new(),
+        };
+        let scheduled_messages = self.scheduled_messages_from_updates(&updates).await?;
+        if !scheduled_messages.is_empty() {
+            return Ok(scheduled_messages);
+        }
+        let mut ids: Vec<i32> = updates
+            .iter()
+            .filter_map(|update| match update {
+                tl::enums::Update::MessageId(update) => Some(update.id),
+                tl::enums::Update::NewMessage(tl::types::UpdateNewMessage { message, .. })
+                | tl::enums::Update::NewChannelMessage(tl::types::UpdateNewChannelMessage { message, .. })
+                | tl::enums::Update::EditMessage(tl::types::UpdateEditMessage { message, .. })
+                | tl::enums::Update::EditChannelMessage(tl::types::UpdateEditChannelMessage { message, .. }) => {
+                    match message {
+                        tl::enums::Message::Message(message) => Some(message.id),
+                        tl::enums::Message::Service(message) => Some(message.id),
+                        tl::enums::Message::Empty(_) => None,
+                    }
                 }
-            }
+                _ => None,
+            })
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let sent = scheduled!(self, "messages.getMessages", self.client().get_messages_by_id(packed_chat, &ids))?;
+        let messages: Vec<NativeMessage> = sent.iter().flatten().map(NativeMessage::from_raw).collect();
+        for message in messages.iter() {
+            self.search_index.index_message(message);
+            self.cache_recent_message(message);
         }
+        Ok(messages)
     }
 
     pub async fn download_media_from_message(
@@ -195,11 +423,14 @@ impl Backend {
         debug!("Downloading media from message with id {}", message_id);
         // let messages_map_pair = self.messages_of_chats.read().await;
         // let messages_map_pair = messages_map_pair.get(&chat_id).unwrap();
-        let packed_chat = self.seen_packed_chats_map.get(&chat_id).unwrap_or_else(|| {
-            error!("Chat with id {} not found in chats_map!", chat_id);
-            panic!("Chat with id {} not found in chats_map!", chat_id)
-        }).clone();
-        let message = self.client.get_messages_by_id(packed_chat, &[message_id]).await;
+        let packed_chat = match self.seen_packed_chats_map.get(&chat_id) {
+            Some(packed_chat) => packed_chat.clone(),
+            None => {
+                error!("Chat with id {} not found in chats_map!", chat_id);
+                return Err(anyhow::anyhow!("Chat with id {} not found in chats_map!", chat_id));
+            }
+        };
+        let message = scheduled!(self, "messages.getMessages", self.client().get_messages_by_id(packed_chat, &[message_id]));
         let mut message = match message {
             Ok(message) => message,
             Err(e) => {
@@ -212,11 +443,9 @@ impl Backend {
             return Err(anyhow::anyhow!("Message not found!"));
         }
         let message = match message.pop() {
-            Some(message) => message.unwrap_or_else(|| {
+            Some(Some(message)) => message,
+            _ => {
                 error!("Message not found!");
-                panic!("Message not found!");
-            }),
-            None => {
                 return Err(anyhow::anyhow!("Message not found!"));
             }
         };
@@ -232,7 +461,8 @@ impl Backend {
             if !std::path::Path::new(&download_dir).exists() {
                 std::fs::create_dir_all(download_dir)?;
             }
-            match message.download_media(download_path.clone()).await {
+            let download = async { scheduled!(self, "upload.getFile", message.download_media(download_path.clone())) };
+            match self.cancellable(download).await {
                 Ok(success) => {
                     if success {
                         debug!("Media downloaded successfully!");
@@ -246,7 +476,7 @@ impl Backend {
                 }
                 Err(e) => {
                     error!("Failed to download media: {e}");
-                    Err(anyhow::Error::from(e))
+                    Err(e)
                 }
             }
         }
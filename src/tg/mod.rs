@@ -7,9 +7,35 @@ mod chat;
 mod reconnect;
 mod utils;
 mod config;
+mod scheduler;
+mod search;
+mod store;
+mod outbox;
+mod proxy;
+mod mtproxy;
+mod lifecycle;
+mod transfer;
+mod catchup;
+pub(crate) mod push;
+mod notify;
+mod reaction;
+mod bot;
+mod inline;
+mod poll;
+mod location;
+mod voice;
+mod sticker;
+mod animation;
+mod webpage;
+mod draft;
+mod schedule;
+mod typing;
+pub(crate) mod session;
 
 use crate::tg::config::MAX_CONCURRENT_REQUESTS;
 use crate::tg::reconnect::HomoReconnectPolicy;
+use crate::tg::scheduler::{scheduled, RequestScheduler};
+use crate::tg::search::{SearchIndex, SEARCH_INDEX_FILE};
 use crate::tg::types::*;
 use anyhow::Result;
 use config::{SIGN_OUT_RETRIES, TELEGRAM_API_HASH, TELEGRAM_API_ID};
@@ -37,11 +63,12 @@ use napi_ohos::threadsafe_function::{
 };
 use napi_ohos::tokio;
 use napi_ohos::tokio::runtime;
-use napi_ohos::tokio::sync::{mpsc, Mutex, MutexGuard, OnceCell, RwLock, Semaphore};
+use napi_ohos::tokio::sync::{mpsc, Mutex, MutexGuard, Notify, OnceCell, RwLock, Semaphore};
 use ohos_hilog_binding::{debug, info, LogLevel, LogType};
 use std::collections::{BTreeMap, VecDeque};
 use std::ffi::CStr;
 use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
 use std::sync::{Arc, LazyLock};
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
@@ -52,26 +79,70 @@ use tracing_subscriber::EnvFilter;
 
 const BASE_PATH: &str = "/data/storage/el2/base/";
 const SESSION_FILE: &str = concatcp!(BASE_PATH, "session");
+/// How long session saves are held back so that bursts of updates cause a single write.
+const SESSION_SAVE_DEBOUNCE: Duration = Duration::from_secs(2);
 
 
 type ChatsMap = HashMap<i64, NativeChat>;
 
 pub struct Backend {
-    client: Client,
+    /// swapped as a whole when the proxy changes, see [`Backend::client`]
+    client: std::sync::RwLock<Client>,
     user: Option<User>,
     login_token: Option<LoginToken>,
     login_state: Option<LoginState>,
     password_token: Option<PasswordToken>,
     seen_packed_chats_map: HashMap<i64, PackedChat>,
+    seen_chats_map: HashMap<i64, NativeSeenChat>,
     chats_map: HashMap<i64, NativeChat>,
+    recent_messages_map: HashMap<i64, BTreeMap<i32, NativeMessage>>,
+    store_dirty: AtomicBool,
     cache_seen_chat_callback: Option<CacheSeenChatCallback>,
     load_chats_callback: Option<LoadChatsCallback>,
     update_chat_callback: Option<UpdateChatCallback>,
     incoming_message_callback: Option<IncomingMessageCallback>,
-    run_handler: Option<tokio::task::JoinHandle<Result<()>>>,
+    run_handler: std::sync::Mutex<Option<tokio::task::JoinHandle<Result<()>>>>,
+    run_token: std::sync::Mutex<CancellationToken>,
+    transfer_token: std::sync::Mutex<CancellationToken>,
+    lifecycle_state: std::sync::Mutex<LifecycleState>,
+    lifecycle_mutex: Mutex<()>,
+    lifecycle_state_callback: Option<LifecycleStateCallback>,
     profile_photo_downloading_set: HashSet<i64>,
     save_session_mutex: Mutex<()>,
+    session_dirty: AtomicBool,
+    session_save_notify: Notify,
     global_semaphore: Semaphore,
+    search_index: SearchIndex,
+    outbox: HashMap<i64, NativeOutboxMessage>,
+    outbox_notify: Notify,
+    outbox_callback: Option<OutboxCallback>,
+    /// the upload progress callbacks given to `queue_message`, not persisted
+    outbox_progress_callbacks: HashMap<i64, Arc<UpdateUploadProgressCallback>>,
+    outbox_handler: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
+    next_temp_id: AtomicI64,
+    scheduler: RequestScheduler,
+    throttle_callback: Option<ThrottleCallback>,
+    connection_state_callback: Option<ConnectionStateCallback>,
+    proxy_config: std::sync::RwLock<Option<NativeProxyConfig>>,
+    proxy_bridge: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
+    catching_up: AtomicBool,
+    catch_up_started_at: AtomicI64,
+    catch_up_processed: AtomicU32,
+    catch_up_callback: Option<CatchUpCallback>,
+    chat_notify_settings_callback: Option<ChatNotifySettingsCallback>,
+    message_reactions_callback: Option<MessageReactionsCallback>,
+    /// poll_id -> (chat_id, message_id)
+    poll_messages: HashMap<i64, (i64, i32)>,
+    poll_callback: Option<PollCallback>,
+    live_locations: HashMap<(i64, i32), location::LiveLocation>,
+    chat_draft_callback: Option<ChatDraftCallback>,
+    /// my user id and name, for the messages grammers doesn't resolve the sender of
+    me: OnceCell<(i64, String)>,
+    scheduled_messages_callback: Option<ScheduledMessagesCallback>,
+    typing_users: HashMap<i64, Vec<typing::TypingUser>>,
+    /// the chats whose typing users are being expired, see `Backend::run_typing_timer`
+    typing_timers: HashSet<i64>,
+    chat_typing_callback: Option<ChatTypingCallback>,
 }
 
 static mut INSTANCE: OnceCell<Backend> = OnceCell::const_new();
@@ -87,11 +158,33 @@ impl Backend {
     async fn init() -> &'static Backend {
         unsafe {
             INSTANCE
-                .get_or_init(|| async { Backend::new().await.unwrap() })
+                .get_or_init(|| async {
+                    let backend = Backend::new().await.unwrap();
+                    // spawned from here so that it runs once, however many callers race
+                    // into `init`; it gets the instance once this initialization is done
+                    tokio::spawn(async { Backend::init().await.run_session_saver().await });
+                    backend
+                })
                 .await
         }
     }
 
+    /// The backend if it has been constructed already, for callers that cannot await.
+    pub(crate) fn try_get_instance() -> Option<&'static Backend> {
+        unsafe { INSTANCE.get() }
+    }
+
+    /// The backend, shared. Prefer this over [`Backend::get_instance`] for everything that
+    /// can run alongside the other tasks using the backend.
+    pub async fn get_shared_instance() -> &'static Backend {
+        unsafe {
+            if !INSTANCE.initialized() {
+                Backend::init().await;
+            }
+            INSTANCE.get().unwrap()
+        }
+    }
+
     pub async fn get_instance() -> &'static mut Backend {
         unsafe {
             if !INSTANCE.initialized() {
@@ -135,33 +228,28 @@ impl Backend {
 
         info!("Constructing Telegram backend...");
 
-        let api_id = TELEGRAM_API_ID.parse()?;
-        let api_hash = TELEGRAM_API_HASH.to_string();
-        info!("Connecting to Telegram...");
         // let session = unsafe {
         //     use std::io::Write;
         //     use std::fs::File;
         //     let file = File::open(SESSION_FILE)?;
         //     Session::load(memmap2::MmapOptions::new().map(&file)?.as_ref())?
         // };
-        let client = Client::connect(Config {
-            session: Session::load_file_or_create(SESSION_FILE)?,
-            // session,
-            api_id,
-            api_hash: api_hash.clone(),
-            params: InitParams {
-                catch_up: true,
-                reconnection_policy: &HomoReconnectPolicy,
-                ..Default::default()
-            },
-        })
-            .await?;
-        info!("Connected!");
+        let proxy_config = proxy::load_proxy_config();
+        let (proxy_url, proxy_bridge) = match proxy::proxy_url(proxy_config.as_ref()).await {
+            Ok(proxy) => proxy,
+            Err(e) => {
+                error!("Failed to apply the saved proxy, connecting directly: {e}");
+                (None, None)
+            }
+        };
+        let client = Backend::connect_client(session::load_session()?, proxy_url).await?;
 
-        Ok(Self {
-            client,
+        let backend = Self {
+            client: std::sync::RwLock::new(client),
             user: None,
             chats_map: HashMap::default(),
+            recent_messages_map: HashMap::default(),
+            store_dirty: AtomicBool::new(false),
             login_token: None,
             login_state: None,
             password_token: None,
@@ -169,23 +257,124 @@ impl Backend {
             load_chats_callback: None,
             update_chat_callback: None,
             incoming_message_callback: None,
-            run_handler: None,
+            run_handler: std::sync::Mutex::new(None),
+            run_token: std::sync::Mutex::new(CancellationToken::new()),
+            transfer_token: std::sync::Mutex::new(CancellationToken::new()),
+            lifecycle_state: std::sync::Mutex::new(LifecycleState::Stopped),
+            lifecycle_mutex: Mutex::new(()),
+            lifecycle_state_callback: None,
             profile_photo_downloading_set: HashSet::default(),
             seen_packed_chats_map: HashMap::default(),
+            seen_chats_map: HashMap::default(),
             save_session_mutex: Mutex::new(()),
+            session_dirty: AtomicBool::new(false),
+            session_save_notify: Notify::new(),
             global_semaphore: Semaphore::new(MAX_CONCURRENT_REQUESTS),
+            search_index: SearchIndex::load_or_default(SEARCH_INDEX_FILE),
+            outbox: outbox::load_outbox().into_iter().map(|m| (m.temp_id, m)).collect(),
+            outbox_notify: Notify::new(),
+            outbox_callback: None,
+            outbox_progress_callbacks: HashMap::new(),
+            outbox_handler: std::sync::Mutex::new(None),
+            next_temp_id: AtomicI64::new(-chrono::Utc::now().timestamp_millis()),
+            scheduler: RequestScheduler::new(),
+            throttle_callback: None,
+            connection_state_callback: None,
+            proxy_config: std::sync::RwLock::new(proxy_config),
+            proxy_bridge: std::sync::Mutex::new(proxy_bridge),
+            catching_up: AtomicBool::new(false),
+            catch_up_started_at: AtomicI64::new(0),
+            catch_up_processed: AtomicU32::new(0),
+            catch_up_callback: None,
+            chat_notify_settings_callback: None,
+            message_reactions_callback: None,
+            poll_messages: HashMap::default(),
+            poll_callback: None,
+            live_locations: HashMap::default(),
+            chat_draft_callback: None,
+            me: OnceCell::new(),
+            scheduled_messages_callback: None,
+            typing_users: HashMap::default(),
+            typing_timers: HashSet::default(),
+            chat_typing_callback: None,
+        };
+        if let Err(e) = backend.load_store() {
+            error!("Failed to load the local store, starting cold: {e}");
+        }
+        Ok(backend)
+    }
+
+    /// The current client. `Client` is a cheap handle, and a clone keeps working on the old
+    /// connection until its request completes if the client gets swapped meanwhile.
+    #[inline]
+    pub(crate) fn client(&self) -> Client {
+        self.client.read().unwrap().clone()
+    }
+
+    async fn connect_client(session: Session, proxy_url: Option<String>) -> Result<Client> {
+        let api_id = TELEGRAM_API_ID.parse()?;
+        let api_hash = TELEGRAM_API_HASH.to_string();
+        info!("Connecting to Telegram...");
+        reconnect::set_connection_state(ConnectionState::Connecting, 0, 0);
+        let client = Client::connect(Config {
+            session,
+            api_id,
+            api_hash: api_hash.clone(),
+            params: InitParams {
+                catch_up: true,
+                // flood waits are handled by our own RequestScheduler
+                flood_sleep_threshold: 0,
+                reconnection_policy: &HomoReconnectPolicy,
+                proxy_url,
+                ..Default::default()
+            },
         })
+            .await?;
+        info!("Connected!");
+        reconnect::set_connection_state(ConnectionState::Connected, 0, 0);
+        Ok(client)
+    }
+
+    /// Ask for the session to be saved. Requests are coalesced, see
+    /// [`Backend::run_session_saver`].
+    #[inline]
+    pub(crate) fn request_session_save(&self) {
+        self.session_dirty.store(true, Ordering::Release);
+        self.session_save_notify.notify_one();
+    }
+
+    /// Save the session at most once per [`SESSION_SAVE_DEBOUNCE`], however many
+    /// updates change it in the meantime.
+    async fn run_session_saver(&'static self) {
+        loop {
+            self.session_save_notify.notified().await;
+            tokio::time::sleep(SESSION_SAVE_DEBOUNCE).await;
+            if self.session_dirty.load(Ordering::Acquire) {
+                self.save_session().await;
+            }
+        }
+    }
+
+    /// Write everything we keep in memory to disk right away, e.g. before the app goes
+    /// to the background or gets stopped.
+    pub async fn flush(&self) {
+        debug!("flush Flushing session, store and search index...");
+        self.save_session().await;
+        self.save_store();
+        self.save_search_index().await;
     }
 
     async fn save_session(&self) {
         debug!("save_session Saving session...");
         let _guard = self.save_session_mutex.lock().await;
         debug!("save_session Session save mutex acquired!");
-        match self.client.session().save_to_file(SESSION_FILE) {
+        self.session_dirty.store(false, Ordering::Release);
+        match session::save_session(self.client().session()) {
             Ok(_) => {
                 debug!("save_session Session saved to {}", SESSION_FILE);
             }
             Err(e) => {
+                self.session_dirty.store(true, Ordering::Release);
                 error!("save_session failed to save the session to {SESSION_FILE}: {e}");
                 // error!("failed to save the session: {e}. Logging out...");
                 // if self.is_logged_in().await {
@@ -202,21 +391,25 @@ impl Backend {
         }
     }
 
-    pub async fn register_device(&self, token: String) -> Result<bool> {
+    /// Subscribe `token` to pushes. With `no_muted`, muted chats don't push at all;
+    /// `other_uids` lists the other accounts signed in on this device, so that their
+    /// pushes don't leak into this one.
+    pub async fn register_device(&self, token: String, no_muted: bool, other_uids: Vec<i64>) -> Result<bool> {
         debug!("Registering device...");
         use grammers_client::grammers_tl_types::functions::account::RegisterDevice;
         let request = RegisterDevice {
-            no_muted: false,
-            token_type: 13, // Huawei Push, https://core.telegram.org/api/push-updates#subscribing-to-notifications
-            token,
+            no_muted,
+            token_type: push::PUSH_TOKEN_TYPE,
+            token: token.clone(),
             app_sandbox: false,
-            secret: vec![],
-            other_uids: vec![],
+            secret: push::push_secret()?.to_vec(),
+            other_uids,
         };
-        let response = self.client.invoke(&request).await;
+        let response = scheduled!(self, "account.registerDevice", self.client().invoke(&request));
         match response {
             Ok(_) => {
                 debug!("Device registered!");
+                push::save_push_token(&token)?;
                 Ok(true)
             }
             Err(e) => {
@@ -226,6 +419,30 @@ impl Backend {
         }
     }
 
+    pub async fn unregister_device(&self, token: String, other_uids: Vec<i64>) -> Result<bool> {
+        debug!("Unregistering device...");
+        use grammers_client::grammers_tl_types::functions::account::UnregisterDevice;
+        let request = UnregisterDevice {
+            token_type: push::PUSH_TOKEN_TYPE,
+            token: token.clone(),
+            other_uids,
+        };
+        let response = scheduled!(self, "account.unregisterDevice", self.client().invoke(&request));
+        match response {
+            Ok(_) => {
+                debug!("Device unregistered!");
+                if push::load_push_token().as_deref() == Some(token.as_str()) {
+                    push::clear_push_token();
+                }
+                Ok(true)
+            }
+            Err(e) => {
+                error!("Failed to unregister device: {e}");
+                Ok(false)
+            }
+        }
+    }
+
     pub(crate) fn register_load_chats_callback(&mut self, cb: LoadChatsCallback) {
         self.load_chats_callback.replace(cb);
     }
@@ -242,15 +459,67 @@ impl Backend {
         self.incoming_message_callback.replace(cb);
     }
 
+    pub(crate) fn register_outbox_callback(&mut self, cb: OutboxCallback) {
+        self.outbox_callback.replace(cb);
+    }
+
+    pub(crate) fn register_throttle_callback(&mut self, cb: ThrottleCallback) {
+        self.throttle_callback.replace(cb);
+    }
+
+    pub(crate) fn register_connection_state_callback(&mut self, cb: ConnectionStateCallback) {
+        self.connection_state_callback.replace(cb);
+    }
+
+    pub(crate) fn register_lifecycle_state_callback(&mut self, cb: LifecycleStateCallback) {
+        self.lifecycle_state_callback.replace(cb);
+    }
+
+    pub(crate) fn register_catch_up_callback(&mut self, cb: CatchUpCallback) {
+        self.catch_up_callback.replace(cb);
+    }
+
+    pub(crate) fn register_chat_notify_settings_callback(&mut self, cb: ChatNotifySettingsCallback) {
+        self.chat_notify_settings_callback.replace(cb);
+    }
+
+    pub(crate) fn register_message_reactions_callback(&mut self, cb: MessageReactionsCallback) {
+        self.message_reactions_callback.replace(cb);
+    }
+
+    pub(crate) fn register_poll_callback(&mut self, cb: PollCallback) {
+        self.poll_callback.replace(cb);
+    }
+
+    pub(crate) fn register_chat_draft_callback(&mut self, cb: ChatDraftCallback) {
+        self.chat_draft_callback.replace(cb);
+    }
+
+    pub(crate) fn register_scheduled_messages_callback(&mut self, cb: ScheduledMessagesCallback) {
+        self.scheduled_messages_callback.replace(cb);
+    }
+
+    pub(crate) fn register_chat_typing_callback(&mut self, cb: ChatTypingCallback) {
+        self.chat_typing_callback.replace(cb);
+    }
+
 
     #[inline]
-    pub async fn is_logged_in(&self) -> bool {
-        self.client.is_authorized().await.unwrap()
+    pub async fn is_logged_in(&self) -> Result<bool> {
+        Ok(scheduled!(self, "auth.isAuthorized", self.client().is_authorized())?)
     }
 
     #[inline]
     pub async fn sign_out(&self) -> bool {
-        if self.client.sign_out().await.is_ok() {
+        // the token dies with the authorization anyway, but the server would keep
+        // pushing to it until it notices
+        if let Some(token) = push::load_push_token() {
+            if let Err(e) = self.unregister_device(token, vec![]).await {
+                error!("sign_out Failed to unregister the device: {e}");
+            }
+            push::clear_push_token();
+        }
+        if scheduled!(self, "auth.logOut", self.client().sign_out()).is_ok() {
             debug!("Signed out successfully!");
             true
         } else {
@@ -261,12 +530,12 @@ impl Backend {
 
     #[inline]
     pub async fn get_me(&self) -> Result<NativeSeenChat> {
-        Ok(NativeSeenChat::from_user(&self.client.get_me().await?))
+        Ok(NativeSeenChat::from_user(&scheduled!(self, "users.getUsers", self.client().get_me())?))
     }
 
     #[inline]
     fn insert_chat_to(&mut self, chat: &NativeChat) {
-        self.chats_map.insert(chat.chat_id, chat.clone());
+        self.cache_chat(chat);
     }
 
     #[inline]
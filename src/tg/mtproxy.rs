@@ -0,0 +1,594 @@
+//! MTProxy support. grammers only speaks SOCKS5, so like HTTP proxies an MTProxy is
+//! reached through the local bridge of `proxy.rs`: grammers talks its own transport to
+//! the bridge, which forwards every packet with the obfuscated2 transport the proxy
+//! expects, wrapped in fake TLS records for `ee` secrets.
+//! https://core.telegram.org/mtproto/mtproto-transports#transport-obfuscation
+
+use crate::tg::types::NativeProxyConfig;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes256;
+use anyhow::Result;
+use base64::Engine;
+use napi_ohos::tokio;
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+
+/// The transport the proxy forwards to Telegram, announced in the obfuscated2 header.
+const INTERMEDIATE_TAG: [u8; 4] = [0xee; 4];
+const PADDED_INTERMEDIATE_TAG: [u8; 4] = [0xdd; 4];
+/// The ClientHello is padded to the size of the one of the official apps.
+const CLIENT_HELLO_LEN: usize = 517;
+/// The largest payload of a TLS record.
+const TLS_MAX_RECORD_LEN: usize = 16384;
+const TLS_CHANGE_CIPHER_SPEC: [u8; 6] = [0x14, 0x03, 0x03, 0x00, 0x01, 0x01];
+const TLS_CIPHER_SUITES: [u8; 30] = [
+    0x13, 0x01, 0x13, 0x02, 0x13, 0x03, 0xc0, 0x2b, 0xc0, 0x2f, 0xc0, 0x2c, 0xc0, 0x30, 0xcc, 0xa9, 0xcc, 0xa8,
+    0xc0, 0x13, 0xc0, 0x14, 0x00, 0x9c, 0x00, 0x9d, 0x00, 0x2f, 0x00, 0x35,
+];
+const TLS_SIGNATURE_ALGORITHMS: [u8; 16] = [
+    0x04, 0x03, 0x08, 0x04, 0x04, 0x01, 0x05, 0x03, 0x08, 0x05, 0x05, 0x01, 0x08, 0x06, 0x06, 0x01,
+];
+
+/// An MTProxy secret, either a plain 16-byte secret, a `dd` (random padding) one or an
+/// `ee` (fake-TLS) one, which is followed by the domain to impersonate.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MtProxySecret {
+    pub(crate) secret: [u8; 16],
+    pub(crate) padded: bool,
+    pub(crate) fake_tls_domain: Option<String>,
+}
+
+impl MtProxySecret {
+    /// Parse a secret as found in `tg://proxy` links, hex or base64url encoded.
+    pub(crate) fn parse(encoded: &str) -> Result<Self> {
+        let encoded = encoded.trim();
+        let bytes = if encoded.len() % 2 == 0 && encoded.chars().all(|c| c.is_ascii_hexdigit()) {
+            (0..encoded.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&encoded[i..i + 2], 16))
+                .collect::<std::result::Result<Vec<u8>, _>>()?
+        } else {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(encoded.trim_end_matches('='))?
+        };
+        let (padded, fake_tls, secret) = match bytes.first() {
+            Some(0xdd) if bytes.len() == 17 => (true, false, &bytes[1..17]),
+            Some(0xee) if bytes.len() > 17 => (true, true, &bytes[1..17]),
+            _ if bytes.len() == 16 => (false, false, &bytes[..]),
+            _ => return Err(anyhow::anyhow!("Invalid MTProxy secret of {} bytes", bytes.len())),
+        };
+        let fake_tls_domain = if fake_tls {
+            Some(String::from_utf8(bytes[17..].to_vec())?)
+        } else {
+            None
+        };
+        Ok(Self {
+            secret: secret.try_into()?,
+            padded,
+            fake_tls_domain,
+        })
+    }
+}
+
+/// The DC grammers connects to at `ip`, which the proxy needs to know where to forward.
+fn dc_id(ip: IpAddr) -> Option<i16> {
+    match ip {
+        IpAddr::V4(ip) => match ip.octets() {
+            [149, 154, 175, 50 | 53] => Some(1),
+            [149, 154, 167, 50 | 51] => Some(2),
+            [149, 154, 175, 100] => Some(3),
+            [149, 154, 167, 91 | 92] => Some(4),
+            [91, 108, 56, 100 | 130 | 190] => Some(5),
+            _ => None,
+        },
+        IpAddr::V6(ip) => match ip.segments() {
+            [0x2001, 0x0b28, 0xf23d, 0xf001, ..] => Some(1),
+            [0x2001, 0x067c, 0x04e8, 0xf002, ..] => Some(2),
+            [0x2001, 0x0b28, 0xf23d, 0xf003, ..] => Some(3),
+            [0x2001, 0x067c, 0x04e8, 0xf004, ..] => Some(4),
+            [0x2001, 0x0b28, 0xf23f, 0xf005, ..] => Some(5),
+            _ => None,
+        },
+    }
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&sha256(&[key]));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner_pad = block.map(|byte| byte ^ 0x36);
+    let outer_pad = block.map(|byte| byte ^ 0x5c);
+    let mut inner = vec![&inner_pad[..]];
+    inner.extend_from_slice(parts);
+    sha256(&[&outer_pad[..], &sha256(&inner)[..]])
+}
+
+/// The CRC32 of the full transport grammers speaks to the bridge.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// AES-256-CTR, with the IV as a 128-bit big-endian counter, keeping its place in the
+/// key stream across calls.
+struct AesCtr {
+    cipher: Aes256,
+    counter: [u8; 16],
+    key_stream: [u8; 16],
+    used: usize,
+}
+
+impl AesCtr {
+    fn new(key: &[u8; 32], iv: &[u8]) -> Self {
+        let mut counter = [0u8; 16];
+        counter.copy_from_slice(iv);
+        Self {
+            cipher: Aes256::new(GenericArray::from_slice(key)),
+            counter,
+            key_stream: [0u8; 16],
+            used: 16,
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            if self.used == self.key_stream.len() {
+                self.key_stream = self.counter;
+                self.cipher.encrypt_block(GenericArray::from_mut_slice(&mut self.key_stream));
+                self.counter = u128::from_be_bytes(self.counter).wrapping_add(1).to_be_bytes();
+                self.used = 0;
+            }
+            *byte ^= self.key_stream[self.used];
+            self.used += 1;
+        }
+    }
+}
+
+/// The 64-byte header opening an obfuscated2 connection to `dc_id`, and the ciphers of
+/// both directions, already past the header.
+fn obfuscated_header(secret: &MtProxySecret, dc_id: i16) -> Result<([u8; 64], AesCtr, AesCtr)> {
+    let mut header = [0u8; 64];
+    loop {
+        getrandom::getrandom(&mut header)?;
+        let first: [u8; 4] = header[..4].try_into()?;
+        // it must not look like the start of another protocol
+        let ambiguous = header[0] == 0xef
+            || matches!(&first, b"HEAD" | b"POST" | b"GET " | b"OPTI" | [0x16, 0x03, 0x01, 0x02])
+            || first == INTERMEDIATE_TAG
+            || first == PADDED_INTERMEDIATE_TAG
+            || header[4..8] == [0; 4];
+        if !ambiguous {
+            break;
+        }
+    }
+    let tag = if secret.padded { PADDED_INTERMEDIATE_TAG } else { INTERMEDIATE_TAG };
+    header[56..60].copy_from_slice(&tag);
+    header[60..62].copy_from_slice(&dc_id.to_le_bytes());
+
+    let mut reversed = header[8..56].to_vec();
+    reversed.reverse();
+    let mut encryptor = AesCtr::new(&sha256(&[&header[8..40], &secret.secret]), &header[40..56]);
+    let decryptor = AesCtr::new(&sha256(&[&reversed[..32], &secret.secret]), &reversed[32..48]);
+    // only the tag and the DC are sent encrypted
+    let mut encrypted = header;
+    encryptor.apply(&mut encrypted);
+    header[56..64].copy_from_slice(&encrypted[56..64]);
+    Ok((header, encryptor, decryptor))
+}
+
+fn with_u16_len(data: &[u8]) -> Vec<u8> {
+    let mut prefixed = (data.len() as u16).to_be_bytes().to_vec();
+    prefixed.extend_from_slice(data);
+    prefixed
+}
+
+fn push_extension(extensions: &mut Vec<u8>, kind: u16, data: &[u8]) {
+    extensions.extend_from_slice(&kind.to_be_bytes());
+    extensions.extend_from_slice(&with_u16_len(data));
+}
+
+/// A TLS 1.3 ClientHello for `domain`, shaped like the one of the official apps, whose
+/// random is the HMAC of the hello, keyed with the secret, with the time mixed in. Also
+/// returns that random, which the proxy signs its answer with.
+fn client_hello(secret: &[u8; 16], domain: &str) -> Result<(Vec<u8>, [u8; 32])> {
+    let mut random = [0u8; 32 + 32 + 6];
+    getrandom::getrandom(&mut random)?;
+    let (session_id, rest) = random.split_at(32);
+    let (key_share, grease_seeds) = rest.split_at(32);
+    // GREASE values are 0x?a?a, the two bytes being equal
+    let grease = |i: usize| {
+        let byte = ((grease_seeds[i] & 0xf0) | 0x0a) as u16;
+        (byte << 8) | byte
+    };
+
+    let mut hello = vec![0x03, 0x03];
+    hello.extend_from_slice(&[0u8; 32]);
+    hello.push(session_id.len() as u8);
+    hello.extend_from_slice(session_id);
+    let mut cipher_suites = grease(0).to_be_bytes().to_vec();
+    cipher_suites.extend_from_slice(&TLS_CIPHER_SUITES);
+    hello.extend_from_slice(&with_u16_len(&cipher_suites));
+    hello.extend_from_slice(&[0x01, 0x00]);
+
+    let mut extensions = Vec::new();
+    push_extension(&mut extensions, grease(1), &[]);
+    let mut server_name = vec![0x00];
+    server_name.extend_from_slice(&with_u16_len(domain.as_bytes()));
+    push_extension(&mut extensions, 0x0000, &with_u16_len(&server_name));
+    push_extension(&mut extensions, 0x0017, &[]);
+    push_extension(&mut extensions, 0xff01, &[0x00]);
+    let mut groups = grease(2).to_be_bytes().to_vec();
+    groups.extend_from_slice(&[0x00, 0x1d, 0x00, 0x17, 0x00, 0x18]);
+    push_extension(&mut extensions, 0x000a, &with_u16_len(&groups));
+    push_extension(&mut extensions, 0x000b, &[0x01, 0x00]);
+    push_extension(&mut extensions, 0x0023, &[]);
+    push_extension(&mut extensions, 0x0010, &with_u16_len(b"\x02h2\x08http/1.1"));
+    push_extension(&mut extensions, 0x0005, &[0x01, 0x00, 0x00, 0x00, 0x00]);
+    push_extension(&mut extensions, 0x000d, &with_u16_len(&TLS_SIGNATURE_ALGORITHMS));
+    push_extension(&mut extensions, 0x0012, &[]);
+    let mut key_shares = grease(2).to_be_bytes().to_vec();
+    key_shares.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x1d, 0x00, 0x20]);
+    key_shares.extend_from_slice(key_share);
+    push_extension(&mut extensions, 0x0033, &with_u16_len(&key_shares));
+    push_extension(&mut extensions, 0x002d, &[0x01, 0x01]);
+    let mut versions = vec![10];
+    versions.extend_from_slice(&grease(3).to_be_bytes());
+    versions.extend_from_slice(&[0x03, 0x04, 0x03, 0x03, 0x03, 0x02, 0x03, 0x01]);
+    push_extension(&mut extensions, 0x002b, &versions);
+    push_extension(&mut extensions, 0x001b, &[0x02, 0x00, 0x02]);
+    push_extension(&mut extensions, grease(4), &[0x00]);
+    // record header, handshake header, the hello, the extensions and the padding header
+    let unpadded_len = 5 + 4 + hello.len() + 2 + extensions.len() + 4;
+    push_extension(&mut extensions, 0x0015, &vec![0u8; CLIENT_HELLO_LEN.saturating_sub(unpadded_len)]);
+    hello.extend_from_slice(&with_u16_len(&extensions));
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&((hello.len() + 4) as u16).to_be_bytes());
+    record.push(0x01);
+    record.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+    record.extend_from_slice(&hello);
+
+    let mut random = hmac_sha256(secret, &[&record]);
+    let timestamp = (chrono::Utc::now().timestamp() as u32).to_le_bytes();
+    for (byte, time) in random[28..].iter_mut().zip(timestamp) {
+        *byte ^= time;
+    }
+    record[11..43].copy_from_slice(&random);
+    Ok((record, random))
+}
+
+/// Send the ClientHello and check that the ServerHello, ChangeCipherSpec and first
+/// application data records that come back were signed with our secret.
+async fn fake_tls_handshake(stream: &mut TcpStream, secret: &[u8; 16], domain: &str) -> Result<()> {
+    let (hello, client_random) = client_hello(secret, domain)?;
+    stream.write_all(&hello).await?;
+    let mut response = Vec::new();
+    for kind in [0x16, 0x14, 0x17] {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await?;
+        if header[0] != kind || header[1..3] != [0x03, 0x03] {
+            return Err(anyhow::anyhow!("MTProxy did not answer like a TLS server"));
+        }
+        let mut body = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+        stream.read_exact(&mut body).await?;
+        response.extend_from_slice(&header);
+        response.extend_from_slice(&body);
+    }
+    if response.len() < 43 {
+        return Err(anyhow::anyhow!("MTProxy sent a truncated ServerHello"));
+    }
+    let server_random: [u8; 32] = response[11..43].try_into()?;
+    response[11..43].fill(0);
+    if hmac_sha256(secret, &[&client_random, &response]) != server_random {
+        return Err(anyhow::anyhow!("MTProxy failed the fake TLS handshake, is the secret right?"));
+    }
+    Ok(())
+}
+
+/// The length of `packet` without the random padding of the padded intermediate
+/// transport, which grammers would not expect.
+fn unpadded_len(packet: &[u8]) -> usize {
+    if packet.len() < 24 {
+        // a transport error code
+        return packet.len().min(4);
+    }
+    if packet[..8] == [0; 8] {
+        // a plaintext message, with the length of its body after the id
+        let len = u32::from_le_bytes([packet[16], packet[17], packet[18], packet[19]]) as usize;
+        return (20 + len).min(packet.len());
+    }
+    // an encrypted message, whose payload is a whole number of AES blocks
+    24 + (packet.len() - 24) / 16 * 16
+}
+
+struct ProxyWriter {
+    stream: OwnedWriteHalf,
+    cipher: AesCtr,
+    fake_tls: bool,
+    /// the first TLS records are preceded by a ChangeCipherSpec one
+    tls_started: bool,
+}
+
+impl ProxyWriter {
+    async fn write_raw(&mut self, data: &[u8]) -> Result<()> {
+        if !self.fake_tls {
+            self.stream.write_all(data).await?;
+            return Ok(());
+        }
+        let mut records = Vec::with_capacity(data.len() + TLS_CHANGE_CIPHER_SPEC.len() + 5);
+        if !self.tls_started {
+            records.extend_from_slice(&TLS_CHANGE_CIPHER_SPEC);
+            self.tls_started = true;
+        }
+        for chunk in data.chunks(TLS_MAX_RECORD_LEN) {
+            records.extend_from_slice(&[0x17, 0x03, 0x03]);
+            records.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            records.extend_from_slice(chunk);
+        }
+        self.stream.write_all(&records).await?;
+        Ok(())
+    }
+
+    async fn write(&mut self, mut data: Vec<u8>) -> Result<()> {
+        self.cipher.apply(&mut data);
+        self.write_raw(&data).await
+    }
+}
+
+struct ProxyReader {
+    stream: OwnedReadHalf,
+    cipher: AesCtr,
+    fake_tls: bool,
+    /// decrypted, not read yet
+    buffer: Vec<u8>,
+}
+
+impl ProxyReader {
+    async fn read_exact(&mut self, len: usize) -> Result<Vec<u8>> {
+        while self.buffer.len() < len {
+            let mut chunk = if self.fake_tls {
+                let mut header = [0u8; 5];
+                self.stream.read_exact(&mut header).await?;
+                if header[..3] != [0x17, 0x03, 0x03] {
+                    return Err(anyhow::anyhow!("MTProxy sent an unexpected TLS record {:02x}", header[0]));
+                }
+                let mut body = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+                self.stream.read_exact(&mut body).await?;
+                body
+            } else {
+                let mut chunk = vec![0u8; 4096];
+                let read = self.stream.read(&mut chunk).await?;
+                if read == 0 {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+                }
+                chunk.truncate(read);
+                chunk
+            };
+            self.cipher.apply(&mut chunk);
+            self.buffer.extend_from_slice(&chunk);
+        }
+        Ok(self.buffer.drain(..len).collect())
+    }
+}
+
+/// An obfuscated connection through an MTProxy, to the DC of the address grammers
+/// asked the bridge for.
+pub(crate) struct MtProxyConnection {
+    reader: ProxyReader,
+    writer: ProxyWriter,
+    padded: bool,
+}
+
+/// Connect to the MTProxy of `config` and open the obfuscated transport to the DC at
+/// `target`. For fake-TLS secrets, the proxy proves it knows the secret on the way.
+pub(crate) async fn connect(config: &NativeProxyConfig, target: SocketAddr) -> Result<MtProxyConnection> {
+    let secret = MtProxySecret::parse(config.secret.as_deref().unwrap_or_default())?;
+    let dc_id = dc_id(target.ip()).ok_or_else(|| anyhow::anyhow!("{target} is not a known Telegram DC"))?;
+    let mut stream = TcpStream::connect((config.host.as_str(), config.port as u16)).await?;
+    if let Some(domain) = secret.fake_tls_domain.as_deref() {
+        fake_tls_handshake(&mut stream, &secret.secret, domain).await?;
+    }
+    let (header, encryptor, decryptor) = obfuscated_header(&secret, dc_id)?;
+    let fake_tls = secret.fake_tls_domain.is_some();
+    let (read, write) = stream.into_split();
+    let mut writer = ProxyWriter {
+        stream: write,
+        cipher: encryptor,
+        fake_tls,
+        tls_started: false,
+    };
+    writer.write_raw(&header).await?;
+    Ok(MtProxyConnection {
+        reader: ProxyReader {
+            stream: read,
+            cipher: decryptor,
+            fake_tls,
+            buffer: Vec::new(),
+        },
+        writer,
+        padded: secret.padded,
+    })
+}
+
+/// Forward the packets grammers sends in the full (or intermediate) transport to the
+/// proxy, in the (padded) intermediate one.
+async fn forward_requests(
+    mut inbound: OwnedReadHalf,
+    mut writer: ProxyWriter,
+    full: bool,
+    mut first_len: Option<u32>,
+    padded: bool,
+) -> Result<()> {
+    loop {
+        let len = match first_len.take() {
+            Some(len) => len,
+            None => inbound.read_u32_le().await?,
+        } as usize;
+        let payload = if full {
+            // the length covers itself, the sequence number and the CRC32
+            if len < 12 {
+                return Err(anyhow::anyhow!("Invalid full transport packet of {len} bytes"));
+            }
+            let mut packet = vec![0u8; len - 4];
+            inbound.read_exact(&mut packet).await?;
+            packet[4..len - 8].to_vec()
+        } else {
+            let mut packet = vec![0u8; len];
+            inbound.read_exact(&mut packet).await?;
+            packet
+        };
+        let mut padding = [0u8; 16];
+        if padded {
+            getrandom::getrandom(&mut padding)?;
+        }
+        let padding = if padded { &padding[..(padding[0] % 16) as usize] } else { &[][..] };
+        let mut packet = ((payload.len() + padding.len()) as u32).to_le_bytes().to_vec();
+        packet.extend_from_slice(&payload);
+        packet.extend_from_slice(padding);
+        writer.write(packet).await?;
+    }
+}
+
+/// Forward the packets of the proxy back to grammers, in the transport it spoke.
+async fn forward_responses(mut reader: ProxyReader, mut outbound: OwnedWriteHalf, full: bool, padded: bool) -> Result<()> {
+    let mut seq = 0u32;
+    loop {
+        let len_bytes = reader.read_exact(4).await?;
+        let len = u32::from_le_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) & 0x7fff_ffff;
+        let mut payload = reader.read_exact(len as usize).await?;
+        if padded {
+            payload.truncate(unpadded_len(&payload));
+        }
+        let packet = if full {
+            let mut packet = ((payload.len() + 12) as u32).to_le_bytes().to_vec();
+            packet.extend_from_slice(&seq.to_le_bytes());
+            packet.extend_from_slice(&payload);
+            packet.extend_from_slice(&crc32(&packet).to_le_bytes());
+            seq = seq.wrapping_add(1);
+            packet
+        } else {
+            let mut packet = (payload.len() as u32).to_le_bytes().to_vec();
+            packet.extend_from_slice(&payload);
+            packet
+        };
+        outbound.write_all(&packet).await?;
+    }
+}
+
+/// Relay grammers' connection to the bridge through `connection`, until either side
+/// closes it.
+pub(crate) async fn relay(inbound: TcpStream, connection: MtProxyConnection) -> Result<()> {
+    let (mut inbound_read, inbound_write) = inbound.into_split();
+    // grammers opens with the length of its first packet in the full transport, and
+    // with a tag in the others
+    let mut first = [0u8; 4];
+    inbound_read.read_exact(&mut first).await?;
+    let (full, first_len) = match first {
+        INTERMEDIATE_TAG => (false, None),
+        [0xef, ..] => return Err(anyhow::anyhow!("The abridged transport is not supported through MTProxy")),
+        len => (true, Some(u32::from_le_bytes(len))),
+    };
+    let MtProxyConnection { reader, writer, padded } = connection;
+    tokio::try_join!(
+        forward_requests(inbound_read, writer, full, first_len, padded),
+        forward_responses(reader, inbound_write, full, padded),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn secrets() {
+        let plain = MtProxySecret::parse("0123456789abcdef0123456789abcdef").unwrap();
+        assert!(!plain.padded && plain.fake_tls_domain.is_none());
+        let padded = MtProxySecret::parse("dd0123456789abcdef0123456789abcdef").unwrap();
+        assert!(padded.padded && padded.fake_tls_domain.is_none());
+        // ee, the secret, then "example.com"
+        let fake_tls = MtProxySecret::parse("ee0123456789abcdef0123456789abcdef6578616d706c652e636f6d").unwrap();
+        assert_eq!(fake_tls.fake_tls_domain.as_deref(), Some("example.com"));
+        assert_eq!(fake_tls.secret, plain.secret);
+        assert!(MtProxySecret::parse("0123").is_err());
+    }
+
+    /// NIST SP 800-38A, F.5.5 CTR-AES256.Encrypt, fed in uneven pieces.
+    #[test]
+    fn aes_ctr_vector() {
+        let key: [u8; 32] = hex("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4").try_into().unwrap();
+        let mut ctr = AesCtr::new(&key, &hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff"));
+        let mut data = hex("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51");
+        let (first, second) = data.split_at_mut(5);
+        ctr.apply(first);
+        ctr.apply(second);
+        assert_eq!(data, hex("601ec313775789a5b7a7f504bbf3d228f443e3ca4d62b59aca84e990cacaf5c5"));
+    }
+
+    /// RFC 4231, test case 2.
+    #[test]
+    fn hmac_vector() {
+        assert_eq!(
+            hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"]).to_vec(),
+            hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+    }
+
+    #[test]
+    fn crc32_vector() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn client_hello_is_signed() {
+        let secret = [7u8; 16];
+        let (hello, random) = client_hello(&secret, "example.com").unwrap();
+        assert_eq!(hello.len(), CLIENT_HELLO_LEN);
+        assert_eq!(&hello[11..43], &random);
+        let mut unsigned = hello.clone();
+        unsigned[11..43].fill(0);
+        let digest = hmac_sha256(&secret, &[&unsigned]);
+        assert_eq!(&digest[..28], &random[..28]);
+    }
+
+    #[test]
+    fn padding_is_stripped() {
+        assert_eq!(unpadded_len(&[0x6c, 0xfe, 0xff, 0xff, 1, 2, 3]), 4);
+        let mut plain = vec![0u8; 20 + 8 + 5];
+        plain[16] = 8;
+        assert_eq!(unpadded_len(&plain), 28);
+        let encrypted = vec![1u8; 24 + 32 + 9];
+        assert_eq!(unpadded_len(&encrypted), 24 + 32);
+    }
+
+    #[test]
+    fn known_dcs() {
+        assert_eq!(dc_id("149.154.167.51".parse().unwrap()), Some(2));
+        assert_eq!(dc_id("2001:b28:f23f:f005::a".parse().unwrap()), Some(5));
+        assert_eq!(dc_id("127.0.0.1".parse().unwrap()), None);
+    }
+}
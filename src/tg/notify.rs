@@ -0,0 +1,96 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::NativePeerNotifySettings;
+use crate::tg::utils::peer_id;
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use grammers_session::PackedChat;
+use log::debug;
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use std::sync::atomic::Ordering;
+
+impl Backend {
+    pub(crate) fn packed_chat(&self, chat_id: i64) -> Result<PackedChat> {
+        self.seen_packed_chats_map
+            .get(&chat_id)
+            .map(|packed_chat| *packed_chat)
+            .ok_or_else(|| anyhow::anyhow!("Chat with id {} not found in seen_packed_chats_map!", chat_id))
+    }
+
+    fn input_notify_peer(&self, chat_id: i64) -> Result<tl::enums::InputNotifyPeer> {
+        Ok(tl::types::InputNotifyPeer {
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+        }
+        .into())
+    }
+
+    pub async fn get_notify_settings(&self, chat_id: i64) -> Result<NativePeerNotifySettings> {
+        let request = tl::functions::account::GetNotifySettings {
+            peer: self.input_notify_peer(chat_id)?,
+        };
+        let settings = scheduled!(self, "account.getNotifySettings", self.client().invoke(&request))?;
+        Ok(NativePeerNotifySettings::from_raw(&settings))
+    }
+
+    /// Replace the notification settings of the chat. Fields left unset fall back to
+    /// the defaults of the chat's scope.
+    pub async fn update_notify_settings(&self, chat_id: i64, settings: NativePeerNotifySettings) -> Result<()> {
+        debug!("update_notify_settings {chat_id}: {:?}", settings);
+        let request = tl::functions::account::UpdateNotifySettings {
+            peer: self.input_notify_peer(chat_id)?,
+            settings: settings.to_input(),
+        };
+        scheduled!(self, "account.updateNotifySettings", self.client().invoke(&request))?;
+        // other sessions get an updateNotifySettings, we don't
+        self.set_chat_notify_settings(chat_id, settings);
+        Ok(())
+    }
+
+    pub async fn mute_chat(&self, chat_id: i64, until: Option<i32>) -> Result<()> {
+        let mut settings = self.cached_notify_settings(chat_id);
+        settings.mute_until = Some(until.unwrap_or(i32::MAX));
+        self.update_notify_settings(chat_id, settings).await
+    }
+
+    pub async fn unmute_chat(&self, chat_id: i64) -> Result<()> {
+        let mut settings = self.cached_notify_settings(chat_id);
+        settings.mute_until = Some(0);
+        self.update_notify_settings(chat_id, settings).await
+    }
+
+    fn cached_notify_settings(&self, chat_id: i64) -> NativePeerNotifySettings {
+        self.chats_map
+            .get(&chat_id)
+            .map(|chat| chat.notify_settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Update the settings of the cached chat and let the app know if they changed.
+    pub(crate) fn set_chat_notify_settings(&self, chat_id: i64, settings: NativePeerNotifySettings) {
+        let Some(mut chat) = self.chats_map.get_mut(&chat_id) else {
+            return;
+        };
+        if chat.notify_settings == settings {
+            return;
+        }
+        chat.notify_settings = settings.clone();
+        drop(chat);
+        self.store_dirty.store(true, Ordering::Release);
+        if let Some(cb) = self.chat_notify_settings_callback.as_ref() {
+            cb.call(Ok((chat_id, settings)), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+
+    /// Handle an updateNotifySettings pushed by the server.
+    pub(crate) fn notify_settings_update_handler(&self, update: &tl::types::UpdateNotifySettings) {
+        match &update.peer {
+            tl::enums::NotifyPeer::Peer(notify_peer) => {
+                let chat_id = peer_id(&notify_peer.peer);
+                debug!("notify_settings_update_handler Settings of {chat_id} changed");
+                self.set_chat_notify_settings(chat_id, NativePeerNotifySettings::from_raw(&update.notify_settings));
+            }
+            // scope defaults and forum topics are not tracked
+            _ => {}
+        }
+    }
+}
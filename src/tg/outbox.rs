@@ -0,0 +1,281 @@
+use crate::tg::transfer::TransferCancelled;
+use crate::tg::types::{NativeOutboxMessage, NativeSendOptions, OutboxState, UpdateUploadProgressCallback};
+use crate::tg::utils::{flood_wait_seconds, is_transient_error, write_atomically};
+use crate::tg::{Backend, BASE_PATH};
+use anyhow::Result;
+use const_format::concatcp;
+use log::{debug, error, info};
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use napi_ohos::tokio;
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+pub(crate) const OUTBOX_FILE: &str = concatcp!(BASE_PATH, "outbox");
+
+const OUTBOX_BASE_RETRY_DELAY_SECS: i64 = 2;
+const OUTBOX_MAX_RETRY_DELAY_SECS: i64 = 300;
+/// How long the outbox worker sleeps when there is nothing due.
+const OUTBOX_IDLE_SECS: u64 = 60;
+
+pub(crate) fn load_outbox() -> Vec<NativeOutboxMessage> {
+    let bytes = match std::fs::read(OUTBOX_FILE) {
+        Ok(bytes) => bytes,
+        Err(_) => return Vec::new(),
+    };
+    match bincode::serde::decode_from_slice::<Vec<NativeOutboxMessage>, _>(&bytes, bincode::config::standard()) {
+        Ok((mut messages, _)) => {
+            // a message that was being sent when the app got killed has to be sent again
+            for message in messages.iter_mut() {
+                if message.state == OutboxState::Sending {
+                    message.state = OutboxState::Pending;
+                }
+            }
+            messages
+        }
+        Err(e) => {
+            error!("load_outbox failed to decode {OUTBOX_FILE}: {e}");
+            Vec::new()
+        }
+    }
+}
+
+fn retry_delay_secs(attempts: u32) -> i64 {
+    OUTBOX_BASE_RETRY_DELAY_SECS
+        .saturating_mul(1 << attempts.min(16))
+        .min(OUTBOX_MAX_RETRY_DELAY_SECS)
+}
+
+impl Backend {
+    fn save_outbox(&self) {
+        let messages: Vec<NativeOutboxMessage> = self.outbox.iter().map(|e| e.value().clone()).collect();
+        let result = bincode::serde::encode_to_vec(&messages, bincode::config::standard())
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Ok(write_atomically(OUTBOX_FILE, &bytes)?));
+        if let Err(e) = result {
+            error!("save_outbox failed to save the outbox to {OUTBOX_FILE}: {e}");
+        }
+    }
+
+    fn emit_outbox_message(&self, message: &NativeOutboxMessage) {
+        if let Some(cb) = self.outbox_callback.as_ref() {
+            cb.call(Ok(message.clone()), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+
+    /// Put a message into the outbox and return it right away as pending. It is sent
+    /// by [`Backend::run_outbox`], which reports the outcome through the outbox callback,
+    /// and the upload progress through `update_upload_progress_callback` until the app
+    /// is restarted.
+    pub fn queue_message(
+        &self,
+        chat_id: i64,
+        text: String,
+        medias: Option<Vec<String>>,
+        options: Option<NativeSendOptions>,
+        update_upload_progress_callback: Option<Arc<UpdateUploadProgressCallback>>,
+    ) -> NativeOutboxMessage {
+        let now = chrono::Utc::now().timestamp();
+        let message = NativeOutboxMessage {
+            temp_id: self.next_temp_id.fetch_sub(1, Ordering::AcqRel),
+            chat_id,
+            text,
+            medias: medias.unwrap_or_default(),
+            created_at: now,
+            attempts: 0,
+            next_attempt_at: now,
+            state: OutboxState::Pending,
+            last_error: None,
+            messages: Vec::new(),
+            options: options.unwrap_or_default(),
+        };
+        debug!("queue_message Queued message {} to chat {}", message.temp_id, chat_id);
+        self.outbox.insert(message.temp_id, message.clone());
+        if let Some(cb) = update_upload_progress_callback {
+            self.outbox_progress_callbacks.insert(message.temp_id, cb);
+        }
+        self.save_outbox();
+        self.outbox_notify.notify_one();
+        message
+    }
+
+    pub fn get_outbox_messages(&self, chat_id: Option<i64>) -> Vec<NativeOutboxMessage> {
+        let mut messages: Vec<NativeOutboxMessage> = self
+            .outbox
+            .iter()
+            .filter(|e| chat_id.map_or(true, |chat_id| e.value().chat_id == chat_id))
+            .map(|e| e.value().clone())
+            .collect();
+        messages.sort_by_key(|m| (m.created_at, -m.temp_id));
+        messages
+    }
+
+    /// Retry a failed message right away.
+    pub fn retry_outbox_message(&self, temp_id: i64) -> bool {
+        let retried = match self.outbox.get_mut(&temp_id) {
+            Some(mut message) if message.state == OutboxState::Failed => {
+                message.state = OutboxState::Pending;
+                message.attempts = 0;
+                message.next_attempt_at = chrono::Utc::now().timestamp();
+                true
+            }
+            _ => false,
+        };
+        if retried {
+            self.save_outbox();
+            self.outbox_notify.notify_one();
+        }
+        retried
+    }
+
+    /// Drop a message that has not been sent yet.
+    pub fn discard_outbox_message(&self, temp_id: i64) -> bool {
+        let discarded = self
+            .outbox
+            .remove_if(&temp_id, |_, message| message.state != OutboxState::Sending)
+            .is_some();
+        if discarded {
+            self.outbox_progress_callbacks.remove(&temp_id);
+            self.save_outbox();
+        }
+        discarded
+    }
+
+    /// Make every pending message due now, e.g. after we got reconnected.
+    pub(crate) fn wake_outbox(&self) {
+        let now = chrono::Utc::now().timestamp();
+        for mut message in self.outbox.iter_mut() {
+            if message.state == OutboxState::Pending {
+                message.next_attempt_at = now;
+            }
+        }
+        self.outbox_notify.notify_one();
+    }
+
+    async fn deliver_outbox_message(&'static self, temp_id: i64) {
+        let message = match self.outbox.get_mut(&temp_id) {
+            Some(mut message) => {
+                message.state = OutboxState::Sending;
+                message.attempts += 1;
+                message.clone()
+            }
+            None => return,
+        };
+        self.emit_outbox_message(&message);
+
+        let medias = (!message.medias.is_empty()).then(|| message.medias.clone());
+        let progress = self.outbox_progress_callbacks.get(&temp_id).map(|cb| cb.clone());
+        let result = self.send_message(message.chat_id, message.text.clone(), medias, message.options.clone(), progress).await;
+
+        let message = match result {
+            Ok(sent) => {
+                info!("deliver_outbox_message Message {} delivered as {:?}", temp_id, sent.iter().map(|m| m.message_id).collect::<Vec<_>>());
+                self.outbox_progress_callbacks.remove(&temp_id);
+                self.outbox.remove(&temp_id).map(|(_, mut message)| {
+                    message.state = OutboxState::Sent;
+                    message.last_error = None;
+                    message.messages = sent;
+                    message
+                })
+            }
+            Err(e) if e.is::<TransferCancelled>() => {
+                info!("deliver_outbox_message Message {} cancelled by shutdown, keeping it", temp_id);
+                self.outbox.get_mut(&temp_id).map(|mut message| {
+                    // the attempt was cut short, it doesn't count
+                    message.state = OutboxState::Pending;
+                    message.attempts -= 1;
+                    message.clone()
+                })
+            }
+            Err(e) => {
+                error!("deliver_outbox_message Failed to deliver message {}: {e}", temp_id);
+                self.outbox.get_mut(&temp_id).map(|mut message| {
+                    let now = chrono::Utc::now().timestamp();
+                    message.last_error = Some(e.to_string());
+                    if let Some(seconds) = flood_wait_seconds(&e) {
+                        // a FLOOD_WAIT_0 would have us resend right away, over and over
+                        message.state = OutboxState::Pending;
+                        message.next_attempt_at = now + (seconds as i64).max(OUTBOX_BASE_RETRY_DELAY_SECS);
+                    } else if is_transient_error(&e) {
+                        message.state = OutboxState::Pending;
+                        message.next_attempt_at = now + retry_delay_secs(message.attempts);
+                    } else {
+                        message.state = OutboxState::Failed;
+                    }
+                    message.clone()
+                })
+            }
+        };
+        self.save_outbox();
+        if let Some(message) = message {
+            self.emit_outbox_message(&message);
+        }
+    }
+
+    /// Deliver the messages in the outbox one by one, until `token` is cancelled. There
+    /// is a single worker, see [`Backend::start`] and [`Backend::shutdown`].
+    pub(crate) async fn run_outbox(&'static self, token: CancellationToken) {
+        while !token.is_cancelled() {
+            let now = chrono::Utc::now().timestamp();
+            let mut pending: Vec<(i64, i64, i64, i64)> = self
+                .outbox
+                .iter()
+                .filter(|message| message.state == OutboxState::Pending)
+                .map(|message| (message.created_at, message.temp_id, message.chat_id, message.next_attempt_at))
+                .collect();
+            // keep the order in which the messages were queued
+            pending.sort_by_key(|(created_at, temp_id, _, _)| (*created_at, -*temp_id));
+            let mut next_attempt_at: Option<i64> = None;
+            let mut blocked_chats = HashSet::new();
+            let mut delivered = false;
+            for (_, temp_id, chat_id, attempt_at) in pending {
+                if blocked_chats.contains(&chat_id) {
+                    continue;
+                }
+                if attempt_at > now {
+                    // the later messages of the chat wait for this one, not to overtake it
+                    blocked_chats.insert(chat_id);
+                    next_attempt_at = Some(next_attempt_at.map_or(attempt_at, |n| n.min(attempt_at)));
+                    continue;
+                }
+                // a send can sit in a flood wait or a reconnect, shutdown doesn't wait for it
+                let interrupted = tokio::select! {
+                    _ = self.deliver_outbox_message(temp_id) => false,
+                    _ = token.cancelled() => true,
+                };
+                if interrupted {
+                    info!("run_outbox Message {} interrupted by shutdown, keeping it", temp_id);
+                    let message = self.outbox.get_mut(&temp_id).map(|mut message| {
+                        // the attempt was cut short, it doesn't count
+                        message.state = OutboxState::Pending;
+                        message.attempts = message.attempts.saturating_sub(1);
+                        message.clone()
+                    });
+                    self.save_outbox();
+                    if let Some(message) = message {
+                        self.emit_outbox_message(&message);
+                    }
+                    return;
+                }
+                delivered = true;
+                if self.outbox.get(&temp_id).is_some_and(|message| message.state == OutboxState::Pending) {
+                    blocked_chats.insert(chat_id);
+                }
+            }
+            if delivered {
+                continue;
+            }
+
+            let idle = next_attempt_at
+                .map(|n| (n - now).max(0) as u64)
+                .unwrap_or(OUTBOX_IDLE_SECS);
+            tokio::select! {
+                _ = token.cancelled() => {}
+                _ = self.outbox_notify.notified() => {}
+                _ = tokio::time::sleep(Duration::from_secs(idle)) => {}
+            }
+        }
+    }
+}
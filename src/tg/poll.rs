@@ -0,0 +1,277 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{NativeMessage, NativePoll, NativePollAnswer, NativePollDraft, NativePollVoter, NativePollVoters};
+use crate::tg::utils::peer_id;
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use log::debug;
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use std::sync::atomic::Ordering;
+
+impl Backend {
+    /// Remember which message carries the poll, updateMessagePoll only has the poll id.
+    pub(crate) fn track_poll(&self, message: &NativeMessage) {
+        if let Some(poll) = message.poll.as_ref() {
+            self.poll_messages.insert(poll.poll_id, (message.chat_id, message.message_id));
+        }
+    }
+
+    /// The poll of the message, from the cache or from the server.
+    async fn get_poll(&self, chat_id: i64, msg_id: i32) -> Result<NativePoll> {
+        let cached = self
+            .recent_messages_map
+            .get(&chat_id)
+            .and_then(|messages| messages.get(&msg_id).and_then(|m| m.poll.clone()));
+        if let Some(poll) = cached {
+            return Ok(poll);
+        }
+        let packed_chat = self.packed_chat(chat_id)?;
+        let message = scheduled!(self, "messages.getMessages", self.client().get_messages_by_id(packed_chat, &[msg_id]))?
+            .pop()
+            .flatten()
+            .ok_or_else(|| anyhow::anyhow!("Message not found!"))?;
+        let message = NativeMessage::from_raw(&message);
+        // cached rather than only tracked, so that it is forgotten once evicted
+        self.cache_recent_message(&message);
+        message.poll.ok_or_else(|| anyhow::anyhow!("Message {msg_id} has no poll"))
+    }
+
+    pub async fn send_poll(&self, chat_id: i64, draft: NativePollDraft) -> Result<Vec<NativeMessage>> {
+        if draft.answers.len() < 2 {
+            return Err(anyhow::anyhow!("A poll needs at least 2 answers"));
+        }
+        if draft.correct_answer.is_some_and(|i| i as usize >= draft.answers.len()) {
+            return Err(anyhow::anyhow!("The correct answer is out of range"));
+        }
+        let poll = NativePoll {
+            poll_id: 0,
+            question: draft.question,
+            answers: draft
+                .answers
+                .into_iter()
+                .enumerate()
+                .map(|(i, text)| NativePollAnswer {
+                    text,
+                    option: vec![i as u8],
+                    voters: None,
+                    chosen: false,
+                    correct: false,
+                })
+                .collect(),
+            closed: false,
+            public_voters: draft.public_voters.unwrap_or(false),
+            multiple_choice: draft.multiple_choice.unwrap_or(false),
+            quiz: draft.correct_answer.is_some(),
+            close_period: draft.close_period,
+            close_date: None,
+            total_voters: None,
+            solution: None,
+        };
+        let media = tl::types::InputMediaPoll {
+            poll: poll.to_raw(),
+            correct_answers: draft.correct_answer.map(|i| vec![vec![i as u8]]),
+            solution_entities: draft.solution.as_ref().map(|_| Vec::new()),
+            solution: draft.solution,
+        };
+        self.send_media_raw(chat_id, media.into(), String::new()).await
+    }
+
+    /// Vote for the answers at `answers`, or retract my vote if it is empty.
+    pub async fn vote_poll(&self, chat_id: i64, msg_id: i32, answers: Vec<u32>) -> Result<NativePoll> {
+        let poll = self.get_poll(chat_id, msg_id).await?;
+        let options = answers
+            .iter()
+            .map(|&i| {
+                poll.answers
+                    .get(i as usize)
+                    .map(|answer| answer.option.clone())
+                    .ok_or_else(|| anyhow::anyhow!("The poll has no answer {i}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let request = tl::functions::messages::SendVote {
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            msg_id,
+            options,
+        };
+        let updates = scheduled!(self, "messages.sendVote", self.client().invoke(&request))?;
+        Ok(self.poll_from_updates(updates).unwrap_or(poll))
+    }
+
+    /// Close the poll, only its author can.
+    pub async fn close_poll(&self, chat_id: i64, msg_id: i32) -> Result<NativePoll> {
+        let mut poll = self.get_poll(chat_id, msg_id).await?;
+        poll.closed = true;
+        let request = tl::functions::messages::EditMessage {
+            no_webpage: false,
+            invert_media: false,
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            id: msg_id,
+            message: None,
+            media: Some(
+                tl::types::InputMediaPoll {
+                    poll: poll.to_raw(),
+                    correct_answers: None,
+                    solution: None,
+                    solution_entities: None,
+                }
+                .into(),
+            ),
+            reply_markup: None,
+            entities: None,
+            schedule_date: None,
+            quick_reply_shortcut_id: None,
+        };
+        let updates = scheduled!(self, "messages.editMessage", self.client().invoke(&request))?;
+        Ok(self.poll_from_updates(updates).unwrap_or(poll))
+    }
+
+    fn poll_from_updates(&self, updates: tl::enums::Updates) -> Option<NativePoll> {
+        let updates = match updates {
+            tl::enums::Updates::Updates(updates) => updates.updates,
+            tl::enums::Updates::Combined(updates) => updates.updates,
+            tl::enums::Updates::UpdateShort(update) => vec![update.update],
+            _ => Vec::new(),
+        };
+        updates
+            .iter()
+            .filter_map(|update| match update {
+                tl::enums::Update::MessagePoll(update) => self.apply_poll_update(update).map(|(_, _, poll)| poll),
+                _ => None,
+            })
+            .last()
+    }
+
+    /// List who voted in a public poll, for a single answer if `answer` is set.
+    pub async fn get_poll_voters(
+        &self,
+        chat_id: i64,
+        msg_id: i32,
+        answer: Option<u32>,
+        offset: Option<String>,
+        limit: i32,
+    ) -> Result<NativePollVoters> {
+        let poll = self.get_poll(chat_id, msg_id).await?;
+        let option = match answer {
+            Some(i) => Some(
+                poll.answers
+                    .get(i as usize)
+                    .map(|answer| answer.option.clone())
+                    .ok_or_else(|| anyhow::anyhow!("The poll has no answer {i}"))?,
+            ),
+            None => None,
+        };
+        let request = tl::functions::messages::GetPollVotes {
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            id: msg_id,
+            option,
+            offset,
+            limit,
+        };
+        let tl::enums::messages::VotesList::List(list) =
+            scheduled!(self, "messages.getPollVotes", self.client().invoke(&request))?;
+        let name_of = |id: i64| -> String {
+            if let Some(seen_chat) = self.seen_chats_map.get(&id) {
+                return seen_chat.full_name.clone();
+            }
+            let user = list.users.iter().find_map(|user| match user {
+                tl::enums::User::User(user) if user.id == id => Some(
+                    [user.first_name.as_deref(), user.last_name.as_deref()]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                _ => None,
+            });
+            let chat = || {
+                list.chats.iter().find_map(|chat| match chat {
+                    tl::enums::Chat::Chat(chat) if chat.id == id => Some(chat.title.clone()),
+                    tl::enums::Chat::Channel(channel) if channel.id == id => Some(channel.title.clone()),
+                    _ => None,
+                })
+            };
+            user.or_else(chat).unwrap_or_default()
+        };
+        let index_of = |option: &Vec<u8>| {
+            poll.answers.iter().position(|answer| &answer.option == option).map(|i| i as u32)
+        };
+        let voters = list
+            .votes
+            .iter()
+            .map(|vote| {
+                let (peer, answers, date) = match vote {
+                    tl::enums::MessagePeerVote::Vote(vote) => (&vote.peer, index_of(&vote.option).into_iter().collect(), vote.date),
+                    tl::enums::MessagePeerVote::InputOption(vote) => (&vote.peer, Vec::new(), vote.date),
+                    tl::enums::MessagePeerVote::Multiple(vote) => {
+                        (&vote.peer, vote.options.iter().filter_map(index_of).collect(), vote.date)
+                    }
+                };
+                let voter_id = peer_id(peer);
+                NativePollVoter {
+                    voter_id,
+                    name: name_of(voter_id),
+                    answers,
+                    date: date as i64,
+                }
+            })
+            .collect();
+        Ok(NativePollVoters {
+            count: list.count,
+            voters,
+            next_offset: list.next_offset,
+        })
+    }
+
+    /// Let the app know about an updateMessagePoll. The run loop is the only one to call
+    /// this, the requests changing a poll only apply the update they get back.
+    pub(crate) fn poll_update_handler(&self, update: &tl::types::UpdateMessagePoll) {
+        let Some((chat_id, msg_id, poll)) = self.apply_poll_update(update) else {
+            return;
+        };
+        debug!("poll_update_handler {}/{}: {:?}", chat_id, msg_id, poll);
+        if let Some(cb) = self.poll_callback.as_ref() {
+            cb.call(Ok((chat_id, msg_id, poll)), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+
+    /// Apply an updateMessagePoll to the cached message, returning the poll as updated.
+    fn apply_poll_update(&self, update: &tl::types::UpdateMessagePoll) -> Option<(i64, i32, NativePoll)> {
+        let (chat_id, msg_id) = *self.poll_messages.get(&update.poll_id)?;
+        let mut messages = self.recent_messages_map.get_mut(&chat_id);
+        let message = messages.as_mut().and_then(|messages| messages.get_mut(&msg_id));
+        let poll = match (message, update.poll.as_ref()) {
+            (Some(message), poll) => {
+                let mut native = match (poll, message.poll.take()) {
+                    (Some(poll), Some(old)) => {
+                        let mut native = NativePoll::from_raw(poll, &update.results);
+                        // `min` results don't know my votes, keep the ones we knew
+                        let tl::enums::PollResults::Results(results) = &update.results;
+                        if results.min {
+                            for answer in native.answers.iter_mut() {
+                                if let Some(old) = old.answers.iter().find(|a| a.option == answer.option) {
+                                    answer.chosen = old.chosen;
+                                    answer.correct = old.correct;
+                                }
+                            }
+                        }
+                        native
+                    }
+                    (Some(poll), None) => NativePoll::from_raw(poll, &update.results),
+                    (None, Some(mut old)) => {
+                        old.apply_results(&update.results);
+                        old
+                    }
+                    (None, None) => return None,
+                };
+                native.poll_id = update.poll_id;
+                message.poll = Some(native.clone());
+                self.store_dirty.store(true, Ordering::Release);
+                native
+            }
+            (None, Some(poll)) => NativePoll::from_raw(poll, &update.results),
+            (None, None) => return None,
+        };
+        drop(messages);
+        Some((chat_id, msg_id, poll))
+    }
+}
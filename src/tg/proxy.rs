@@ -0,0 +1,373 @@
+use crate::tg::mtproxy::{self, MtProxySecret};
+use crate::tg::session::{seal, unseal};
+use crate::tg::types::{NativeProxyConfig, NativeProxyTestResult, ProxyType};
+use crate::tg::utils::write_atomically;
+use crate::tg::{Backend, BASE_PATH};
+use anyhow::Result;
+use base64::Engine;
+use const_format::concatcp;
+use log::{debug, error, info};
+use napi_ohos::tokio;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub(crate) const PROXY_FILE: &str = concatcp!(BASE_PATH, "proxy");
+
+/// Telegram DC 2, used as the destination when testing a proxy.
+const PROXY_TEST_TARGET: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(149, 154, 167, 51)), 443);
+const PROXY_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the bridge waits before accepting again after it failed to.
+const BRIDGE_ACCEPT_BACKOFF: Duration = Duration::from_millis(500);
+
+fn validate(config: &NativeProxyConfig) -> Result<()> {
+    if config.host.is_empty() || config.port == 0 || config.port > u16::MAX as u32 {
+        return Err(anyhow::anyhow!("Invalid proxy address {}:{}", config.host, config.port));
+    }
+    if config.proxy_type == ProxyType::MtProxy {
+        MtProxySecret::parse(config.secret.as_deref().unwrap_or_default())?;
+    }
+    // SOCKS5 sends their lengths in a single byte
+    if let Some((username, password)) = config.auth() {
+        if username.len() > u8::MAX as usize || password.len() > u8::MAX as usize {
+            return Err(anyhow::anyhow!("Proxy username and password must be at most 255 bytes"));
+        }
+    }
+    Ok(())
+}
+
+/// Percent-encode `s` for the userinfo part of a URL.
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Random hex credentials, for the local HTTP bridge.
+fn random_credential() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// The proxy credentials are sealed with the session key, like the session itself. A
+/// config saved in plaintext is sealed again as soon as it is loaded with a key set.
+pub(crate) fn load_proxy_config() -> Option<NativeProxyConfig> {
+    let bytes = std::fs::read(PROXY_FILE).ok()?;
+    let decoded = unseal(bytes.clone()).and_then(|plaintext| {
+        let (config, _) = bincode::serde::decode_from_slice::<Option<NativeProxyConfig>, _>(
+            &plaintext,
+            bincode::config::standard(),
+        )?;
+        Ok((config, plaintext == bytes))
+    });
+    match decoded {
+        Ok((config, plaintext)) => {
+            if plaintext {
+                if let Err(e) = save_proxy_config(config.as_ref()) {
+                    error!("load_proxy_config failed to seal {PROXY_FILE}: {e}");
+                }
+            }
+            config
+        }
+        Err(e) => {
+            error!("load_proxy_config failed to decode {PROXY_FILE}: {e}");
+            None
+        }
+    }
+}
+
+fn save_proxy_config(config: Option<&NativeProxyConfig>) -> Result<()> {
+    match config {
+        Some(config) => {
+            let bytes = bincode::serde::encode_to_vec(Some(config), bincode::config::standard())?;
+            write_atomically(PROXY_FILE, &seal(PROXY_FILE, bytes)?)?
+        }
+        None => {
+            if std::path::Path::new(PROXY_FILE).exists() {
+                std::fs::remove_file(PROXY_FILE)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn write_target(stream: &mut TcpStream, target: SocketAddr) -> Result<()> {
+    let mut request = vec![0x05, 0x01, 0x00];
+    match target.ip() {
+        IpAddr::V4(ip) => {
+            request.push(0x01);
+            request.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            request.push(0x04);
+            request.extend_from_slice(&ip.octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request).await?;
+    Ok(())
+}
+
+/// Open a tunnel to `target` through the SOCKS5 proxy `stream` is connected to.
+async fn socks5_connect(stream: &mut TcpStream, target: SocketAddr, auth: Option<(&str, &str)>) -> Result<()> {
+    match auth {
+        Some(_) => stream.write_all(&[0x05, 0x02, 0x00, 0x02]).await?,
+        None => stream.write_all(&[0x05, 0x01, 0x00]).await?,
+    }
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    match (reply, auth) {
+        ([0x05, 0x00], _) => {}
+        ([0x05, 0x02], Some((username, password))) => {
+            let mut request = vec![0x01, u8::try_from(username.len())?];
+            request.extend_from_slice(username.as_bytes());
+            request.push(u8::try_from(password.len())?);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await?;
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0x00 {
+                return Err(anyhow::anyhow!("SOCKS5 authentication failed"));
+            }
+        }
+        _ => return Err(anyhow::anyhow!("SOCKS5 proxy refused our authentication methods")),
+    }
+
+    write_target(stream, target).await?;
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    if header[1] != 0x00 {
+        return Err(anyhow::anyhow!("SOCKS5 proxy failed to connect with code {}", header[1]));
+    }
+    let address_len = match header[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        atyp => return Err(anyhow::anyhow!("SOCKS5 proxy replied with address type {atyp}")),
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+/// Open a tunnel to `target` through the HTTP proxy `stream` is connected to.
+async fn http_connect(stream: &mut TcpStream, target: SocketAddr, auth: Option<(&str, &str)>) -> Result<()> {
+    let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    if let Some((username, password)) = auth {
+        let credentials = base64::engine::general_purpose::STANDARD.encode(format!("{username}:{password}"));
+        request.push_str(&format!("Proxy-Authorization: Basic {credentials}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // read the response head byte by byte, so we do not eat into the tunnelled data
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        response.push(stream.read_u8().await?);
+        if response.len() > 8192 {
+            return Err(anyhow::anyhow!("HTTP proxy response is too long"));
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(anyhow::anyhow!("HTTP proxy refused to connect: {status}"));
+    }
+    Ok(())
+}
+
+/// Read the destination of a SOCKS5 CONNECT request from `stream`, once it authenticated
+/// with `credentials`.
+async fn accept_socks5(stream: &mut TcpStream, credentials: &(String, String)) -> Result<SocketAddr> {
+    let mut greeting = [0u8; 2];
+    stream.read_exact(&mut greeting).await?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if !methods.contains(&0x02) {
+        stream.write_all(&[0x05, 0xff]).await?;
+        return Err(anyhow::anyhow!("SOCKS5 client did not offer username/password authentication"));
+    }
+    stream.write_all(&[0x05, 0x02]).await?;
+
+    let _version = stream.read_u8().await?;
+    let mut username = vec![0u8; stream.read_u8().await? as usize];
+    stream.read_exact(&mut username).await?;
+    let mut password = vec![0u8; stream.read_u8().await? as usize];
+    stream.read_exact(&mut password).await?;
+    if username != credentials.0.as_bytes() || password != credentials.1.as_bytes() {
+        stream.write_all(&[0x01, 0x01]).await?;
+        return Err(anyhow::anyhow!("SOCKS5 client sent wrong credentials"));
+    }
+    stream.write_all(&[0x01, 0x00]).await?;
+
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let ip = match header[3] {
+        0x01 => {
+            let mut octets = [0u8; 4];
+            stream.read_exact(&mut octets).await?;
+            IpAddr::from(octets)
+        }
+        0x04 => {
+            let mut octets = [0u8; 16];
+            stream.read_exact(&mut octets).await?;
+            IpAddr::from(octets)
+        }
+        atyp => return Err(anyhow::anyhow!("Unsupported SOCKS5 address type {atyp}")),
+    };
+    let port = stream.read_u16().await?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// grammers only speaks SOCKS5, so HTTP proxies and MTProxies are reached through a local
+/// SOCKS5 listener that tunnels every connection with HTTP CONNECT, or through the
+/// obfuscated transport of `mtproxy.rs`. Other apps can reach the listener too, so it
+/// only serves whoever knows the random credentials of this session.
+async fn spawn_bridge(config: NativeProxyConfig) -> Result<(String, tokio::task::JoinHandle<()>)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let credentials = std::sync::Arc::new((random_credential()?, random_credential()?));
+    let url = format!("socks5://{}:{}@{}", credentials.0, credentials.1, listener.local_addr()?);
+    let handler = tokio::spawn(async move {
+        loop {
+            let (mut inbound, _) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // e.g. out of file descriptors, which won't be better right away
+                    error!("spawn_bridge failed to accept: {e}");
+                    tokio::time::sleep(BRIDGE_ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            let config = config.clone();
+            let credentials = credentials.clone();
+            tokio::spawn(async move {
+                let tunnel = async {
+                    let target = accept_socks5(&mut inbound, &credentials).await?;
+                    if config.proxy_type == ProxyType::MtProxy {
+                        let connection = mtproxy::connect(&config, target).await?;
+                        inbound.write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+                        return mtproxy::relay(inbound, connection).await;
+                    }
+                    let mut outbound = TcpStream::connect((config.host.as_str(), config.port as u16)).await?;
+                    http_connect(&mut outbound, target, config.auth()).await?;
+                    inbound.write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+                    tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await?;
+                    anyhow::Ok(())
+                };
+                if let Err(e) = tunnel.await {
+                    debug!("spawn_bridge tunnel closed: {e}");
+                }
+            });
+        }
+    });
+    Ok((url, handler))
+}
+
+impl NativeProxyConfig {
+    fn auth(&self) -> Option<(&str, &str)> {
+        self.username
+            .as_deref()
+            .map(|username| (username, self.password.as_deref().unwrap_or_default()))
+    }
+}
+
+/// The `proxy_url` to hand over to grammers for `config`, and the bridge task serving
+/// it if one is needed.
+pub(crate) async fn proxy_url(
+    config: Option<&NativeProxyConfig>,
+) -> Result<(Option<String>, Option<tokio::task::JoinHandle<()>>)> {
+    let Some(config) = config else {
+        return Ok((None, None));
+    };
+    validate(config)?;
+    match config.proxy_type {
+        ProxyType::Socks5 => {
+            let url = match config.auth() {
+                Some((username, password)) => format!(
+                    "socks5://{}:{}@{}:{}",
+                    percent_encode(username),
+                    percent_encode(password),
+                    config.host,
+                    config.port
+                ),
+                None => format!("socks5://{}:{}", config.host, config.port),
+            };
+            Ok((Some(url), None))
+        }
+        ProxyType::Http | ProxyType::MtProxy => {
+            let (url, handler) = spawn_bridge(config.clone()).await?;
+            Ok((Some(url), Some(handler)))
+        }
+    }
+}
+
+impl Backend {
+    pub fn get_proxy(&self) -> Option<NativeProxyConfig> {
+        self.proxy_config.read().unwrap().clone()
+    }
+
+    /// Switch to `config` (or to a direct connection if `None`). The session is kept,
+    /// so we stay logged in.
+    pub async fn set_proxy(&'static self, config: Option<NativeProxyConfig>) -> Result<()> {
+        let _guard = self.lifecycle_mutex.lock().await;
+        info!("set_proxy Switching proxy to {:?}", config.as_ref().map(|c| (&c.proxy_type, &c.host, c.port)));
+        let (proxy_url, bridge) = proxy_url(config.as_ref()).await?;
+        let was_running = self.is_update_loop_running();
+        self.stop_update_loop().await;
+        self.save_session().await;
+        if let Err(e) = self.reconnect_client(proxy_url, bridge).await {
+            if was_running {
+                self.start_update_loop();
+            }
+            return Err(e);
+        }
+        save_proxy_config(config.as_ref())?;
+        *self.proxy_config.write().unwrap() = config;
+        if was_running {
+            self.start_update_loop();
+        }
+        self.wake_outbox();
+        Ok(())
+    }
+
+    /// Check whether `config` can reach Telegram and how long it takes to do so.
+    pub async fn test_proxy(&self, config: NativeProxyConfig) -> NativeProxyTestResult {
+        let started = Instant::now();
+        let result = tokio::time::timeout(PROXY_TEST_TIMEOUT, async {
+            validate(&config)?;
+            let connect = || TcpStream::connect((config.host.as_str(), config.port as u16));
+            match config.proxy_type {
+                ProxyType::Socks5 => socks5_connect(&mut connect().await?, PROXY_TEST_TARGET, config.auth()).await?,
+                ProxyType::Http => http_connect(&mut connect().await?, PROXY_TEST_TARGET, config.auth()).await?,
+                // fake-TLS proxies prove they know the secret, the others stay silent
+                ProxyType::MtProxy => {
+                    mtproxy::connect(&config, PROXY_TEST_TARGET).await?;
+                }
+            }
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out after {:?}", PROXY_TEST_TIMEOUT)));
+        let latency_ms = started.elapsed().as_millis() as i64;
+        debug!("test_proxy {}:{} result: {:?} in {}ms", config.host, config.port, result, latency_ms);
+        match result {
+            Ok(_) => NativeProxyTestResult {
+                reachable: true,
+                latency_ms,
+                error: None,
+            },
+            Err(e) => NativeProxyTestResult {
+                reachable: false,
+                latency_ms: -1,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use crate::tg::session::{seal, unseal};
+use crate::tg::types::NativePushNotification;
+use crate::tg::utils::write_atomically;
+use crate::tg::BASE_PATH;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecrypt, KeyInit};
+use aes::Aes256;
+use anyhow::Result;
+use base64::Engine;
+use const_format::concatcp;
+use log::{debug, error, info};
+use serde::Deserialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+pub(crate) const PUSH_SECRET_FILE: &str = concatcp!(BASE_PATH, "push_secret");
+pub(crate) const PUSH_TOKEN_FILE: &str = concatcp!(BASE_PATH, "push_token");
+pub(crate) const PUSH_SECRET_LEN: usize = 256;
+/// Huawei Push, https://core.telegram.org/api/push-updates#subscribing-to-notifications
+pub(crate) const PUSH_TOKEN_TYPE: i32 = 13;
+
+/// Pushes are encrypted like messages coming from the server.
+/// https://core.telegram.org/api/push-updates#encryption
+const SERVER_X: usize = 8;
+
+static PUSH_SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// The secret push payloads are encrypted with, generated on first use.
+pub(crate) fn push_secret() -> Result<&'static [u8]> {
+    if let Some(secret) = PUSH_SECRET.get() {
+        return Ok(secret);
+    }
+    let secret = match std::fs::read(PUSH_SECRET_FILE) {
+        Ok(data) => unseal(data)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!("push_secret Generating a new push secret");
+            let mut secret = vec![0u8; PUSH_SECRET_LEN];
+            getrandom::getrandom(&mut secret)?;
+            write_atomically(PUSH_SECRET_FILE, &seal(PUSH_SECRET_FILE, secret.clone())?)?;
+            secret
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if secret.len() != PUSH_SECRET_LEN {
+        return Err(anyhow::anyhow!("The push secret is {} bytes long, expected {PUSH_SECRET_LEN}", secret.len()));
+    }
+    Ok(PUSH_SECRET.get_or_init(|| secret))
+}
+
+/// The token we last registered, so that signing out can unregister it.
+pub(crate) fn load_push_token() -> Option<String> {
+    std::fs::read_to_string(PUSH_TOKEN_FILE).ok().filter(|token| !token.is_empty())
+}
+
+pub(crate) fn save_push_token(token: &str) -> Result<()> {
+    write_atomically(PUSH_TOKEN_FILE, token.as_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn clear_push_token() {
+    if let Err(e) = std::fs::remove_file(PUSH_TOKEN_FILE) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            error!("clear_push_token Failed to remove {PUSH_TOKEN_FILE}: {e}");
+        }
+    }
+}
+
+/// The 64 lower-order bits of SHA1 of the key, as for MTProto auth keys.
+fn key_id(secret: &[u8]) -> [u8; 8] {
+    let hash = Sha1::digest(secret);
+    hash[12..20].try_into().unwrap()
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    parts.iter().for_each(|part| hasher.update(part));
+    hasher.finalize().into()
+}
+
+/// Derive the AES key and IV from `msg_key` as in MTProto 2.0.
+fn derive_aes_key_iv(secret: &[u8], msg_key: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let x = SERVER_X;
+    let a = sha256(&[msg_key, &secret[x..x + 36]]);
+    let b = sha256(&[&secret[40 + x..40 + x + 36], msg_key]);
+    let mut key = [0u8; 32];
+    key[..8].copy_from_slice(&a[..8]);
+    key[8..24].copy_from_slice(&b[8..24]);
+    key[24..].copy_from_slice(&a[24..]);
+    let mut iv = [0u8; 32];
+    iv[..8].copy_from_slice(&b[..8]);
+    iv[8..24].copy_from_slice(&a[8..24]);
+    iv[24..].copy_from_slice(&b[24..]);
+    (key, iv)
+}
+
+/// AES-256 in infinite garble extension mode, as used by MTProto.
+fn ige_decrypt(ciphertext: &[u8], key: &[u8; 32], iv: &[u8; 32]) -> Vec<u8> {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    let mut previous_ciphertext: [u8; 16] = iv[..16].try_into().unwrap();
+    let mut previous_plaintext: [u8; 16] = iv[16..].try_into().unwrap();
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+    for chunk in ciphertext.chunks_exact(16) {
+        let mut block = [0u8; 16];
+        block.iter_mut().zip(chunk.iter().zip(previous_plaintext.iter())).for_each(|(b, (c, p))| *b = c ^ p);
+        cipher.decrypt_block(GenericArray::from_mut_slice(&mut block));
+        block.iter_mut().zip(previous_ciphertext.iter()).for_each(|(b, c)| *b ^= c);
+        plaintext.extend_from_slice(&block);
+        previous_ciphertext.copy_from_slice(chunk);
+        previous_plaintext = block;
+    }
+    plaintext
+}
+
+/// Decrypt an encrypted push payload with `secret`: the 8-byte key id, the 16-byte
+/// message key, then the AES-IGE encrypted JSON prefixed with its length.
+pub(crate) fn decrypt_payload(secret: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 24 + 16 || (data.len() - 24) % 16 != 0 {
+        return Err(anyhow::anyhow!("Malformed push payload of {} bytes", data.len()));
+    }
+    if data[..8] != key_id(secret) {
+        return Err(anyhow::anyhow!("The push payload was encrypted with another secret"));
+    }
+    let msg_key = &data[8..24];
+    let (key, iv) = derive_aes_key_iv(secret, msg_key);
+    let plaintext = ige_decrypt(&data[24..], &key, &iv);
+    let msg_key_large = sha256(&[&secret[88 + SERVER_X..88 + SERVER_X + 32], &plaintext]);
+    if &msg_key_large[8..24] != msg_key {
+        return Err(anyhow::anyhow!("Push payload authentication failed"));
+    }
+    let len = u32::from_le_bytes(plaintext[..4].try_into().unwrap()) as usize;
+    if len > plaintext.len() - 4 {
+        return Err(anyhow::anyhow!("Push payload length {len} out of bounds"));
+    }
+    Ok(plaintext[4..4 + len].to_vec())
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PushPayload {
+    #[serde(default)]
+    loc_key: String,
+    #[serde(default)]
+    loc_args: Vec<String>,
+    #[serde(default)]
+    custom: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    mute: Option<serde_json::Value>,
+}
+
+/// Telegram sends numbers in `custom` as strings.
+fn custom_i64(custom: &HashMap<String, serde_json::Value>, key: &str) -> Option<i64> {
+    match custom.get(key)? {
+        serde_json::Value::String(s) => s.parse().ok(),
+        value => value.as_i64(),
+    }
+}
+
+fn is_truthy(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Bool(b) => *b,
+        serde_json::Value::String(s) => s == "1",
+        value => value.as_i64().is_some_and(|n| n != 0),
+    }
+}
+
+fn parse_notification(json: &[u8]) -> Result<NativePushNotification> {
+    let payload: PushPayload = serde_json::from_slice(json)?;
+    let custom = &payload.custom;
+    let chat_id = custom_i64(custom, "channel_id")
+        .or_else(|| custom_i64(custom, "chat_id"))
+        .or_else(|| custom_i64(custom, "from_id"));
+    let preview_text = payload
+        .message
+        .clone()
+        .or_else(|| (payload.loc_args.len() > 1).then(|| payload.loc_args.last().unwrap().clone()));
+    Ok(NativePushNotification {
+        loc_key: payload.loc_key.clone(),
+        chat_id,
+        message_id: custom_i64(custom, "msg_id").map(|id| id as i32),
+        sender_name: payload.loc_args.first().cloned(),
+        preview_text,
+        silent: custom.get("silent").is_some_and(is_truthy),
+        mute: payload.mute.as_ref().is_some_and(is_truthy),
+        loc_args: payload.loc_args,
+    })
+}
+
+/// Turn a push payload as delivered to the app into a notification. `payload` is either
+/// the `{"p": "<base64url>"}` JSON Telegram sends when a secret is registered, or the
+/// raw encrypted bytes.
+pub(crate) fn decrypt_push_payload(payload: &[u8]) -> Result<NativePushNotification> {
+    let encrypted = match serde_json::from_slice::<HashMap<String, serde_json::Value>>(payload) {
+        Ok(json) => match json.get("p").and_then(|p| p.as_str()) {
+            Some(p) => base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(p.trim_end_matches('='))?,
+            // registered without a secret, the payload is in the clear
+            None => return parse_notification(payload),
+        },
+        Err(_) => payload.to_vec(),
+    };
+    let json = decrypt_payload(push_secret()?, &encrypted)?;
+    debug!("decrypt_push_payload Decrypted {} bytes", json.len());
+    parse_notification(&json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockEncrypt;
+
+    fn secret() -> Vec<u8> {
+        (0..PUSH_SECRET_LEN).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    fn ige_encrypt(plaintext: &[u8], key: &[u8; 32], iv: &[u8; 32]) -> Vec<u8> {
+        let cipher = Aes256::new(GenericArray::from_slice(key));
+        let mut previous_ciphertext: [u8; 16] = iv[..16].try_into().unwrap();
+        let mut previous_plaintext: [u8; 16] = iv[16..].try_into().unwrap();
+        let mut ciphertext = Vec::with_capacity(plaintext.len());
+        for chunk in plaintext.chunks_exact(16) {
+            let mut block = [0u8; 16];
+            block.iter_mut().zip(chunk.iter().zip(previous_ciphertext.iter())).for_each(|(b, (p, c))| *b = p ^ c);
+            cipher.encrypt_block(GenericArray::from_mut_slice(&mut block));
+            block.iter_mut().zip(previous_plaintext.iter()).for_each(|(b, p)| *b ^= p);
+            ciphertext.extend_from_slice(&block);
+            previous_plaintext.copy_from_slice(chunk);
+            previous_ciphertext = block;
+        }
+        ciphertext
+    }
+
+    /// Encrypt `json` the way the server does.
+    fn encrypt_payload(secret: &[u8], json: &[u8]) -> Vec<u8> {
+        let mut plaintext = (json.len() as u32).to_le_bytes().to_vec();
+        plaintext.extend_from_slice(json);
+        while plaintext.len() % 16 != 0 || plaintext.len() < json.len() + 4 + 12 {
+            plaintext.push(0xab);
+        }
+        let msg_key_large = sha256(&[&secret[88 + SERVER_X..88 + SERVER_X + 32], &plaintext]);
+        let msg_key = &msg_key_large[8..24];
+        let (key, iv) = derive_aes_key_iv(secret, msg_key);
+        let mut data = key_id(secret).to_vec();
+        data.extend_from_slice(msg_key);
+        data.extend_from_slice(&ige_encrypt(&plaintext, &key, &iv));
+        data
+    }
+
+    const JSON: &[u8] = br#"{"loc_key":"MESSAGE_TEXT","loc_args":["Alice","Hello"],"custom":{"from_id":"42","msg_id":"7"}}"#;
+
+    #[test]
+    fn round_trip() {
+        let secret = secret();
+        let data = encrypt_payload(&secret, JSON);
+        let json = decrypt_payload(&secret, &data).unwrap();
+        assert_eq!(json, JSON);
+
+        let notification = parse_notification(&json).unwrap();
+        assert_eq!(notification.loc_key, "MESSAGE_TEXT");
+        assert_eq!(notification.chat_id, Some(42));
+        assert_eq!(notification.message_id, Some(7));
+        assert_eq!(notification.sender_name.as_deref(), Some("Alice"));
+        assert_eq!(notification.preview_text.as_deref(), Some("Hello"));
+    }
+
+    /// Encrypted independently of this module, following the MTProto 2.0 spec with x = 8
+    /// (https://core.telegram.org/mtproto/description#defining-aes-key-and-initialization-vector)
+    /// in Python with `hashlib` and the AES of `cryptography`, for the secret below.
+    const VECTOR: &str = concat!(
+        "fc4eaf55f743da3f7783280479819e960fadc424f5ebd2dd5633b7549a9c89a28adb11b35acc83a5ecf44993817bd539",
+        "92d652a41efa59c4c7874a064b703e8b5c165f95de9d6f7db116a153cf83b3b7a3913c64554ee377e6d8bd9f68ca9bd3",
+        "1b1dbb0896d668dc17ab8f0ec8e071ad623c9fe1a0a7e28fc3723c12ecc4fc8c32233fa44858fdd2bc2c81c07548efb5",
+        "0f1a0b1b4d7e851ec9c525a2eaeea46bb7dbb828dbde111d",
+    );
+    const VECTOR_JSON: &[u8] = br#"{"loc_key":"CHAT_MESSAGE_TEXT","loc_args":["Bob","Friends","Hi there"],"custom":{"chat_id":"1234","msg_id":"99"}}"#;
+
+    #[test]
+    fn known_vector() {
+        let secret: Vec<u8> = (0..PUSH_SECRET_LEN).map(|i| (i * 13 + 5) as u8).collect();
+        let data: Vec<u8> = (0..VECTOR.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&VECTOR[i..i + 2], 16).unwrap())
+            .collect();
+        let json = decrypt_payload(&secret, &data).unwrap();
+        assert_eq!(json, VECTOR_JSON);
+
+        let notification = parse_notification(&json).unwrap();
+        assert_eq!(notification.chat_id, Some(1234));
+        assert_eq!(notification.message_id, Some(99));
+        assert_eq!(notification.preview_text.as_deref(), Some("Hi there"));
+    }
+
+    #[test]
+    fn tampered_msg_key() {
+        let secret = secret();
+        let mut data = encrypt_payload(&secret, JSON);
+        data[8] ^= 0x01;
+        let e = decrypt_payload(&secret, &data).unwrap_err();
+        assert_eq!(e.to_string(), "Push payload authentication failed");
+    }
+
+    #[test]
+    fn wrong_key_id() {
+        let secret = secret();
+        let mut data = encrypt_payload(&secret, JSON);
+        data[0] ^= 0x01;
+        let e = decrypt_payload(&secret, &data).unwrap_err();
+        assert_eq!(e.to_string(), "The push payload was encrypted with another secret");
+    }
+}
@@ -0,0 +1,130 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{NativeAvailableReactions, NativeReaction, NativeReactionCount};
+use crate::tg::utils::peer_id;
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use grammers_session::PackedType;
+use log::debug;
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use std::sync::atomic::Ordering;
+
+impl Backend {
+    /// Set my reactions to `msg_id`, replacing the previous ones. An empty `reactions`
+    /// removes them. Returns the reactions of the message after the change.
+    pub async fn send_reaction(
+        &self,
+        chat_id: i64,
+        msg_id: i32,
+        reactions: Vec<NativeReaction>,
+        big: bool,
+    ) -> Result<Vec<NativeReactionCount>> {
+        let request = tl::functions::messages::SendReaction {
+            big,
+            add_to_recent: true,
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            msg_id,
+            reaction: Some(reactions.iter().map(NativeReaction::to_raw).collect()),
+        };
+        let updates = scheduled!(self, "messages.sendReaction", self.client().invoke(&request))?;
+        let updates = match updates {
+            tl::enums::Updates::Updates(updates) => updates.updates,
+            tl::enums::Updates::Combined(updates) => updates.updates,
+            tl::enums::Updates::UpdateShort(update) => vec![update.update],
+            _ => Vec::new(),
+        };
+        let mut result = Vec::new();
+        for update in updates {
+            if let tl::enums::Update::MessageReactions(update) = update {
+                if update.msg_id == msg_id {
+                    result = self.message_reactions_update_handler(&update);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    pub async fn get_available_reactions(&self, chat_id: i64) -> Result<NativeAvailableReactions> {
+        let packed_chat = self.packed_chat(chat_id)?;
+        let full_chat = match packed_chat.ty {
+            // private chats allow every reaction
+            PackedType::User | PackedType::Bot => {
+                return Ok(NativeAvailableReactions {
+                    all: true,
+                    allow_custom: true,
+                    reactions: Vec::new(),
+                })
+            }
+            PackedType::Chat => {
+                let request = tl::functions::messages::GetFullChat { chat_id: packed_chat.id };
+                scheduled!(self, "messages.getFullChat", self.client().invoke(&request))?
+            }
+            PackedType::Megagroup | PackedType::Broadcast | PackedType::Gigagroup => {
+                let request = tl::functions::channels::GetFullChannel {
+                    channel: tl::types::InputChannel {
+                        channel_id: packed_chat.id,
+                        access_hash: packed_chat.access_hash.unwrap_or(0),
+                    }
+                    .into(),
+                };
+                scheduled!(self, "channels.getFullChannel", self.client().invoke(&request))?
+            }
+        };
+        let tl::enums::messages::ChatFull::Full(full_chat) = full_chat;
+        let available_reactions = match full_chat.full_chat {
+            tl::enums::ChatFull::Full(full) => full.available_reactions,
+            tl::enums::ChatFull::ChannelFull(full) => full.available_reactions,
+        };
+        Ok(match available_reactions {
+            None | Some(tl::enums::ChatReactions::None) => NativeAvailableReactions {
+                all: false,
+                allow_custom: false,
+                reactions: Vec::new(),
+            },
+            Some(tl::enums::ChatReactions::All(all)) => NativeAvailableReactions {
+                all: true,
+                allow_custom: all.allow_custom,
+                reactions: Vec::new(),
+            },
+            Some(tl::enums::ChatReactions::Some(some)) => NativeAvailableReactions {
+                all: false,
+                allow_custom: false,
+                reactions: some.reactions.iter().filter_map(NativeReaction::from_raw).collect(),
+            },
+        })
+    }
+
+    /// Apply an updateMessageReactions to the cached message and let the app know.
+    pub(crate) fn message_reactions_update_handler(
+        &self,
+        update: &tl::types::UpdateMessageReactions,
+    ) -> Vec<NativeReactionCount> {
+        let chat_id = peer_id(&update.peer);
+        let mut reactions = NativeReactionCount::from_reactions(&update.reactions);
+        let tl::enums::MessageReactions::Reactions(raw) = &update.reactions;
+        let mut messages = self.recent_messages_map.get_mut(&chat_id);
+        let message = messages.as_mut().and_then(|messages| messages.get_mut(&update.msg_id));
+        if let Some(message) = message {
+            // `min` updates don't know which reactions are mine, keep what we knew
+            if raw.min {
+                for reaction in reactions.iter_mut() {
+                    reaction.chosen = message
+                        .reactions
+                        .iter()
+                        .any(|old| old.reaction == reaction.reaction && old.chosen);
+                }
+            }
+            message.reactions = reactions.clone();
+            self.store_dirty.store(true, Ordering::Release);
+        }
+        drop(messages);
+        debug!("message_reactions_update_handler {}/{}: {:?}", chat_id, update.msg_id, reactions);
+        if let Some(cb) = self.message_reactions_callback.as_ref() {
+            cb.call(
+                Ok((chat_id, update.msg_id, reactions.clone())),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+        reactions
+    }
+}
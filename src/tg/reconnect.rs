@@ -1,30 +1,173 @@
+use crate::tg::proxy::proxy_url;
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{ConnectionState, NativeConnectionState};
 use crate::tg::Backend;
 use anyhow::Result;
 use grammers_mtsender::ReconnectionPolicy;
 use log::{debug, error};
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use napi_ohos::tokio;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
 use std::time::Duration;
 
+const DEFAULT_RECONNECT_BASE_DELAY_MS: u64 = 500;
+const DEFAULT_RECONNECT_MAX_DELAY_MS: u64 = 60_000;
+/// How often the sender checks back while we are waiting for the network.
+const NETWORK_WAIT_POLL_MS: u64 = 5_000;
+
+static RECONNECT_BASE_DELAY_MS: AtomicU64 = AtomicU64::new(DEFAULT_RECONNECT_BASE_DELAY_MS);
+static RECONNECT_MAX_DELAY_MS: AtomicU64 = AtomicU64::new(DEFAULT_RECONNECT_MAX_DELAY_MS);
+/// 0 means retrying forever.
+static RECONNECT_MAX_ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+static NETWORK_AVAILABLE: AtomicBool = AtomicBool::new(true);
+static CONNECTION_STATE: AtomicU8 = AtomicU8::new(ConnectionState::Connecting as u8);
+
+impl ConnectionState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            v if v == ConnectionState::Connected as u8 => ConnectionState::Connected,
+            v if v == ConnectionState::Updating as u8 => ConnectionState::Updating,
+            v if v == ConnectionState::WaitingForNetwork as u8 => ConnectionState::WaitingForNetwork,
+            v if v == ConnectionState::BackingOff as u8 => ConnectionState::BackingOff,
+            v if v == ConnectionState::Disconnected as u8 => ConnectionState::Disconnected,
+            _ => ConnectionState::Connecting,
+        }
+    }
+}
+
+pub(crate) fn connection_state() -> ConnectionState {
+    ConnectionState::from_u8(CONNECTION_STATE.load(Ordering::Acquire))
+}
+
+/// Move the connection state machine to `state` and stream it to ArkTS.
+pub(crate) fn set_connection_state(state: ConnectionState, attempt: u32, retry_in_ms: i64) {
+    let previous = CONNECTION_STATE.swap(state as u8, Ordering::AcqRel);
+    // backing off is reported on every attempt, the other states only when they change
+    if previous == state as u8 && state != ConnectionState::BackingOff {
+        return;
+    }
+    debug!("Connection state: {:?} (attempt {}, retry in {}ms)", state, attempt, retry_in_ms);
+    if let Some(cb) = Backend::try_get_instance().and_then(|b| b.connection_state_callback.as_ref()) {
+        cb.call(
+            Ok(NativeConnectionState {
+                state,
+                attempt,
+                retry_in_ms,
+            }),
+            ThreadsafeFunctionCallMode::NonBlocking,
+        );
+    }
+}
+
+/// A request went through, so whatever retrying we reported is over.
+pub(crate) fn note_connected() {
+    if !matches!(connection_state(), ConnectionState::Connected | ConnectionState::Updating) {
+        set_connection_state(ConnectionState::Connected, 0, 0);
+    }
+}
+
+/// Exponential backoff with "equal jitter": a random delay in `[d / 2, d]`, where `d`
+/// doubles on every attempt up to the configured cap.
+fn backoff_delay_ms(attempts: usize) -> u64 {
+    let base = RECONNECT_BASE_DELAY_MS.load(Ordering::Acquire);
+    let cap = RECONNECT_MAX_DELAY_MS.load(Ordering::Acquire).max(base);
+    let delay = base.saturating_mul(1u64 << attempts.min(32)).min(cap);
+    let jitter = RandomState::new().build_hasher().finish() % (delay / 2 + 1);
+    delay / 2 + jitter
+}
+
 pub(crate) struct HomoReconnectPolicy;
 
 impl ReconnectionPolicy for HomoReconnectPolicy {
     fn should_retry(&self, attempts: usize) -> ControlFlow<(), Duration> {
         debug!("Reconnecting attempt {}", attempts);
-        // retry after 1 second for developing phase
-        ControlFlow::Continue(Duration::from_secs(1))
+        if !NETWORK_AVAILABLE.load(Ordering::Acquire) {
+            set_connection_state(ConnectionState::WaitingForNetwork, attempts as u32, NETWORK_WAIT_POLL_MS as i64);
+            return ControlFlow::Continue(Duration::from_millis(NETWORK_WAIT_POLL_MS));
+        }
+        let max_attempts = RECONNECT_MAX_ATTEMPTS.load(Ordering::Acquire);
+        if max_attempts != 0 && attempts >= max_attempts as usize {
+            error!("Giving up reconnecting after {} attempts", attempts);
+            set_connection_state(ConnectionState::Disconnected, attempts as u32, -1);
+            return ControlFlow::Break(());
+        }
+        let delay = backoff_delay_ms(attempts);
+        set_connection_state(ConnectionState::BackingOff, attempts as u32, delay as i64);
+        ControlFlow::Continue(Duration::from_millis(delay))
     }
 }
 
 impl Backend {
+    /// Configure the backoff of [`HomoReconnectPolicy`]. `max_attempts` of `None` or 0
+    /// keeps retrying forever.
+    pub fn set_reconnect_policy(&self, base_delay_ms: u32, max_delay_ms: u32, max_attempts: Option<u32>) {
+        RECONNECT_BASE_DELAY_MS.store(base_delay_ms.max(1) as u64, Ordering::Release);
+        RECONNECT_MAX_DELAY_MS.store(max_delay_ms as u64, Ordering::Release);
+        RECONNECT_MAX_ATTEMPTS.store(max_attempts.unwrap_or(0), Ordering::Release);
+    }
+
+    pub fn get_connection_state(&self) -> ConnectionState {
+        connection_state()
+    }
+
+    /// Called from the OS network callback. Losing the network pauses the retries,
+    /// getting it back triggers an immediate reconnect.
+    pub fn set_network_available(&'static self, available: bool) {
+        let was_available = NETWORK_AVAILABLE.swap(available, Ordering::AcqRel);
+        if !available {
+            set_connection_state(ConnectionState::WaitingForNetwork, 0, -1);
+        } else if !was_available || connection_state() == ConnectionState::Disconnected {
+            tokio::spawn(async {
+                if let Err(e) = self.restart_connection().await {
+                    error!("set_network_available Failed to reconnect: {e}");
+                }
+            });
+        }
+    }
+
+    /// Replace the connection with a new one right away, e.g. once the network is back
+    /// or after [`HomoReconnectPolicy`] gave up. The sender of the old connection may be
+    /// sleeping through a long backoff, which its policy has no way to cut short.
+    pub async fn restart_connection(&'static self) -> Result<()> {
+        let _guard = self.lifecycle_mutex.lock().await;
+        debug!("restart_connection Reconnecting from state {:?}", connection_state());
+        let (proxy_url, bridge) = proxy_url(self.get_proxy().as_ref()).await?;
+        let was_running = self.is_update_loop_running();
+        self.stop_update_loop().await;
+        self.save_session().await;
+        let result = self.reconnect_client(proxy_url, bridge).await;
+        if was_running {
+            self.start_update_loop();
+        }
+        if let Err(e) = result {
+            set_connection_state(ConnectionState::Disconnected, 0, -1);
+            return Err(e);
+        }
+        self.wake_outbox();
+        Ok(())
+    }
+
     #[inline]
     pub async fn reconnect(&self) -> bool {
-        match self.client.is_authorized().await {
+        if !NETWORK_AVAILABLE.load(Ordering::Acquire) {
+            debug!("Not reconnecting without network");
+            return false;
+        }
+        set_connection_state(ConnectionState::Connecting, 0, 0);
+        match scheduled!(self, "auth.isAuthorized", self.client().is_authorized()) {
             Ok(is_authorized) => {
                 debug!("Reconnected with is_authorized: {is_authorized}");
+                set_connection_state(ConnectionState::Connected, 0, 0);
+                self.wake_outbox();
                 true
             }
             Err(e) => {
                 error!("Reconnect failed: {e}");
+                // nothing retries from here, so don't claim a backoff is pending
+                set_connection_state(ConnectionState::Disconnected, 0, -1);
                 false
             }
         }
@@ -1,34 +1,82 @@
-use crate::tg::types::{NativeChat, NativeMessage, NativeSeenChat};
-use crate::tg::utils::get_profile_photo_path_and_count;
+use crate::tg::catchup::CATCH_UP_IDLE_TIMEOUT;
+use crate::tg::reconnect::{connection_state, set_connection_state};
+use crate::tg::types::{ConnectionState, NativeChat, NativeMessage, NativeSeenChat};
+use crate::tg::utils::{get_profile_photo_path_and_count, peer_id};
 use crate::tg::{Backend, SESSION_FILE};
 use anyhow::Result;
-use grammers_client::Update;
+use grammers_client::{grammers_tl_types as tl, Update};
 use grammers_session::Session;
 use log::{debug, info};
 use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
 use napi_ohos::tokio;
 use ohos_hilog_binding::debug;
 use std::collections::BTreeMap;
+use tokio_util::sync::CancellationToken;
 
 impl Backend {
-    pub async fn run(&'static self) -> Result<()> {
+    /// Handle updates until `token` is cancelled. The update at hand is always handled
+    /// to completion, so cancelling drains the loop instead of cutting it off.
+    pub async fn run(&'static self, token: CancellationToken) -> Result<()> {
+        // with `catch_up` set, the first updates we get are the ones we missed
+        set_connection_state(ConnectionState::Updating, 0, 0);
+        self.begin_catch_up();
+        // `set_proxy` restarts the loop after swapping the client
+        let client = self.client();
         loop {
             debug!("tg::Backend::run() Waiting for next update...");
-            match self.client.next_update().await? {
+            let update = tokio::select! {
+                biased;
+                _ = token.cancelled() => {
+                    debug!("tg::Backend::run() Cancelled");
+                    return Ok(());
+                }
+                update = client.next_update() => update?,
+                // nothing left to catch up on once the backlog stops flowing
+                _ = tokio::time::sleep(CATCH_UP_IDLE_TIMEOUT), if self.is_catching_up() => {
+                    self.finish_catch_up();
+                    continue;
+                }
+            };
+            if connection_state() != ConnectionState::Connected {
+                set_connection_state(ConnectionState::Connected, 0, 0);
+            }
+            match update {
                 Update::NewMessage(ref raw_message) => {
-                    self.incoming_message_handler(raw_message).await;
+                    let live = self.track_update(Some(raw_message.date().timestamp()));
+                    self.incoming_message_handler(raw_message, live).await;
+                }
+                Update::Raw(ref update) => {
+                    self.track_update(None);
+                    self.raw_update_handler(update).await;
+                }
+                _ => {
+                    self.track_update(None);
+                    info!("Other update are not implemented currently.")
                 }
-                _ => info!("Other update are not implemented currently."),
             }
-            tokio::spawn(self.save_session());
+            self.request_session_save();
         }
     }
 
-    pub async fn set_run_handler(&mut self, handler: tokio::task::JoinHandle<Result<()>>) {
-        self.run_handler.replace(handler);
-    }
-
-    pub async fn get_run_handler(&mut self) -> Option<tokio::task::JoinHandle<Result<()>>> {
-        self.run_handler.take()
+    /// Handle an update grammers doesn't wrap, live or from a difference fetched to catch up.
+    pub(crate) async fn raw_update_handler(&'static self, update: &tl::enums::Update) {
+        match update {
+            tl::enums::Update::NotifySettings(update) => self.notify_settings_update_handler(update),
+            tl::enums::Update::MessageReactions(update) => self.message_reactions_update_handler(update),
+            tl::enums::Update::MessagePoll(update) => self.poll_update_handler(update),
+            tl::enums::Update::DraftMessage(update) => self.draft_update_handler(update),
+            tl::enums::Update::NewScheduledMessage(update) => self.new_scheduled_message_handler(update).await,
+            tl::enums::Update::DeleteScheduledMessages(update) => self.delete_scheduled_messages_handler(update),
+            tl::enums::Update::UserTyping(update) => {
+                self.typing_update_handler(update.user_id, update.user_id, &update.action)
+            }
+            tl::enums::Update::ChatUserTyping(update) => {
+                self.typing_update_handler(update.chat_id, peer_id(&update.from_id), &update.action)
+            }
+            tl::enums::Update::ChannelUserTyping(update) => {
+                self.typing_update_handler(update.channel_id, peer_id(&update.from_id), &update.action)
+            }
+            _ => info!("Other update are not implemented currently."),
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,143 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{checked_schedule_date, NativeMessage};
+use crate::tg::utils::peer_id;
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use log::{debug, error};
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+
+impl Backend {
+    /// My user id and name, fetched once.
+    async fn me(&self) -> Result<&(i64, String)> {
+        self.me
+            .get_or_try_init(|| async {
+                if let Some(user) = self.user.as_ref() {
+                    return Ok((user.id(), user.full_name()));
+                }
+                let user = scheduled!(self, "users.getUsers", self.client().get_me())?;
+                Ok((user.id(), user.full_name()))
+            })
+            .await
+    }
+
+    /// Wrap a raw message grammers didn't, resolving its sender from the chats we have seen.
+    pub(crate) async fn native_message_from_tl(&self, raw: &tl::enums::Message) -> Result<Option<NativeMessage>> {
+        let tl::enums::Message::Message(raw) = raw else {
+            return Ok(None);
+        };
+        let sender_id = match (&raw.from_id, &raw.peer_id) {
+            (Some(from), _) => peer_id(from),
+            // channel posts are sent by the channel itself
+            (None, tl::enums::Peer::Channel(channel)) => channel.channel_id,
+            (None, tl::enums::Peer::User(user)) if !raw.out => user.user_id,
+            _ => self.me().await?.0,
+        };
+        let (my_id, my_name) = self.me().await?;
+        let sender_name = if sender_id == *my_id {
+            my_name.clone()
+        } else {
+            self.seen_chats_map
+                .get(&sender_id)
+                .map(|chat| chat.full_name.clone())
+                .unwrap_or_default()
+        };
+        Ok(Some(NativeMessage::from_tl(raw, sender_id, sender_name)))
+    }
+
+    pub(crate) async fn scheduled_messages_from_updates(&self, updates: &[tl::enums::Update]) -> Result<Vec<NativeMessage>> {
+        let mut messages = Vec::new();
+        for update in updates {
+            if let tl::enums::Update::NewScheduledMessage(update) = update {
+                messages.extend(self.native_message_from_tl(&update.message).await?);
+            }
+        }
+        Ok(messages)
+    }
+
+    /// The messages waiting to be sent in the chat, the next one first.
+    pub async fn get_scheduled_messages(&self, chat_id: i64) -> Result<Vec<NativeMessage>> {
+        let request = tl::functions::messages::GetScheduledHistory {
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            hash: 0,
+        };
+        let raw_messages = match scheduled!(self, "messages.getScheduledHistory", self.client().invoke(&request))? {
+            tl::enums::messages::Messages::Messages(messages) => messages.messages,
+            tl::enums::messages::Messages::Slice(messages) => messages.messages,
+            tl::enums::messages::Messages::ChannelMessages(messages) => messages.messages,
+            tl::enums::messages::Messages::NotModified(_) => Vec::new(),
+        };
+        let mut messages = Vec::with_capacity(raw_messages.len());
+        for raw in raw_messages.iter() {
+            messages.extend(self.native_message_from_tl(raw).await?);
+        }
+        messages.sort_by_key(|message| message.timestamp);
+        Ok(messages)
+    }
+
+    /// Move the scheduled message `msg_id` to `date`.
+    pub async fn reschedule_message(&self, chat_id: i64, msg_id: i32, date: i64) -> Result<Vec<NativeMessage>> {
+        let packed_chat = self.packed_chat(chat_id)?;
+        let request = tl::functions::messages::EditMessage {
+            no_webpage: false,
+            invert_media: false,
+            peer: packed_chat.to_input_peer(),
+            id: msg_id,
+            message: None,
+            media: None,
+            reply_markup: None,
+            entities: None,
+            schedule_date: Some(checked_schedule_date(date)?),
+            quick_reply_shortcut_id: None,
+        };
+        let updates = scheduled!(self, "messages.editMessage", self.client().invoke(&request))?;
+        self.messages_from_updates(packed_chat, updates).await
+    }
+
+    /// Send the scheduled messages right away, returns them as sent.
+    pub async fn send_scheduled_now(&self, chat_id: i64, ids: Vec<i32>) -> Result<Vec<NativeMessage>> {
+        let packed_chat = self.packed_chat(chat_id)?;
+        let request = tl::functions::messages::SendScheduledMessages {
+            peer: packed_chat.to_input_peer(),
+            id: ids.clone(),
+        };
+        let updates = scheduled!(self, "messages.sendScheduledMessages", self.client().invoke(&request))?;
+        self.emit_scheduled_messages(chat_id, Vec::new(), ids);
+        self.messages_from_updates(packed_chat, updates).await
+    }
+
+    pub async fn delete_scheduled_messages(&self, chat_id: i64, ids: Vec<i32>) -> Result<()> {
+        let request = tl::functions::messages::DeleteScheduledMessages {
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            id: ids.clone(),
+        };
+        scheduled!(self, "messages.deleteScheduledMessages", self.client().invoke(&request))?;
+        self.emit_scheduled_messages(chat_id, Vec::new(), ids);
+        Ok(())
+    }
+
+    fn emit_scheduled_messages(&self, chat_id: i64, messages: Vec<NativeMessage>, deleted: Vec<i32>) {
+        if let Some(cb) = self.scheduled_messages_callback.as_ref() {
+            cb.call(Ok((chat_id, messages, deleted)), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+
+    /// A message got scheduled, or a scheduled one edited, possibly on another device.
+    pub(crate) async fn new_scheduled_message_handler(&self, update: &tl::types::UpdateNewScheduledMessage) {
+        match self.native_message_from_tl(&update.message).await {
+            Ok(Some(message)) => {
+                debug!("new_scheduled_message_handler {}/{}", message.chat_id, message.message_id);
+                self.emit_scheduled_messages(message.chat_id, vec![message], Vec::new());
+            }
+            Ok(None) => {}
+            Err(e) => error!("new_scheduled_message_handler Failed to resolve the message: {e}"),
+        }
+    }
+
+    /// Scheduled messages were deleted, or sent since their time came.
+    pub(crate) fn delete_scheduled_messages_handler(&self, update: &tl::types::UpdateDeleteScheduledMessages) {
+        let chat_id = peer_id(&update.peer);
+        debug!("delete_scheduled_messages_handler {chat_id}: {:?}", update.messages);
+        self.emit_scheduled_messages(chat_id, Vec::new(), update.messages.clone());
+    }
+}
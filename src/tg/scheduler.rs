@@ -0,0 +1,186 @@
+use crate::tg::reconnect;
+use crate::tg::types::NativeThrottleEvent;
+use crate::tg::Backend;
+use dashmap::DashMap as HashMap;
+use grammers_client::client::auth::AuthorizationError;
+use grammers_client::SignInError;
+use grammers_mtsender::InvocationError;
+use log::{debug, error};
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use napi_ohos::tokio;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Flood waits up to this many seconds are slept through instead of being returned.
+pub(crate) const DEFAULT_FLOOD_SLEEP_THRESHOLD: u32 = 60;
+
+/// The minimum interval between two calls of the same method. Methods not listed
+/// here are not rate limited, apart from the flood waits Telegram asks for.
+const METHOD_MIN_INTERVALS: &[(&str, Duration)] = &[
+    ("messages.getDialogs", Duration::from_millis(500)),
+    ("messages.getHistory", Duration::from_millis(300)),
+    ("messages.getMessages", Duration::from_millis(200)),
+    ("messages.sendMessage", Duration::from_millis(100)),
+    ("messages.sendMultiMedia", Duration::from_millis(500)),
+    ("account.registerDevice", Duration::from_secs(1)),
+];
+
+/// Errors that may carry a `FLOOD_WAIT_X` from Telegram.
+pub(crate) trait RequestError {
+    fn invocation_error(&self) -> Option<&InvocationError>;
+
+    fn flood_wait_seconds(&self) -> Option<u32> {
+        match self.invocation_error() {
+            Some(InvocationError::Rpc(rpc)) if rpc.name == "FLOOD_WAIT" || rpc.name == "FLOOD_PREMIUM_WAIT" => {
+                rpc.value.or(Some(0))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl RequestError for InvocationError {
+    fn invocation_error(&self) -> Option<&InvocationError> {
+        Some(self)
+    }
+}
+
+impl RequestError for AuthorizationError {
+    fn invocation_error(&self) -> Option<&InvocationError> {
+        match self {
+            AuthorizationError::Invoke(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl RequestError for SignInError {
+    fn invocation_error(&self) -> Option<&InvocationError> {
+        match self {
+            SignInError::Other(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The transfers of grammers report their RPC errors wrapped in an `io::Error`.
+impl RequestError for std::io::Error {
+    fn invocation_error(&self) -> Option<&InvocationError> {
+        self.get_ref().and_then(|e| e.downcast_ref::<InvocationError>())
+    }
+}
+
+/// Keeps track of when each method may be called next.
+pub(crate) struct RequestScheduler {
+    next_slots: HashMap<&'static str, Instant>,
+    flood_sleep_threshold: AtomicU32,
+}
+
+impl RequestScheduler {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_slots: HashMap::default(),
+            flood_sleep_threshold: AtomicU32::new(DEFAULT_FLOOD_SLEEP_THRESHOLD),
+        }
+    }
+
+    fn min_interval(method: &str) -> Option<Duration> {
+        METHOD_MIN_INTERVALS
+            .iter()
+            .find(|(m, _)| *m == method)
+            .map(|(_, interval)| *interval)
+    }
+
+    /// Reserve the next slot for `method` and wait until it comes.
+    async fn acquire(&self, method: &'static str) {
+        let now = Instant::now();
+        let slot = {
+            let mut next_slot = self.next_slots.entry(method).or_insert(now);
+            let slot = (*next_slot).max(now);
+            *next_slot = slot + Self::min_interval(method).unwrap_or_default();
+            slot
+        };
+        if slot > now {
+            debug!("RequestScheduler {method} throttled for {:?}", slot - now);
+            tokio::time::sleep_until(slot).await;
+        }
+    }
+
+    /// Nobody gets to call `method` before `seconds` have passed.
+    fn hold(&self, method: &'static str, seconds: u32) {
+        let until = Instant::now() + Duration::from_secs(seconds as u64);
+        let mut next_slot = self.next_slots.entry(method).or_insert(until);
+        *next_slot = (*next_slot).max(until);
+    }
+}
+
+impl Backend {
+    pub fn set_flood_sleep_threshold(&self, seconds: u32) {
+        self.scheduler.flood_sleep_threshold.store(seconds, Ordering::Release);
+    }
+
+    pub(crate) async fn before_request(&self, method: &'static str) {
+        self.scheduler.acquire(method).await;
+    }
+
+    #[inline]
+    pub(crate) fn on_request_ok(&self) {
+        reconnect::note_connected();
+    }
+
+    fn report_throttle(&self, method: &'static str, seconds: u32, will_retry: bool) {
+        if let Some(cb) = self.throttle_callback.as_ref() {
+            cb.call(
+                Ok(NativeThrottleEvent {
+                    method: method.to_string(),
+                    wait_seconds: seconds,
+                    will_retry,
+                }),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+    }
+
+    /// Decide whether a request to `method` that failed with `e` should be sent again.
+    pub(crate) async fn on_request_error<E: RequestError>(&self, method: &'static str, e: &E) -> ControlFlow<()> {
+        let Some(seconds) = e.flood_wait_seconds() else {
+            return ControlFlow::Break(());
+        };
+        self.scheduler.hold(method, seconds);
+        let will_retry = seconds <= self.scheduler.flood_sleep_threshold.load(Ordering::Acquire);
+        self.report_throttle(method, seconds, will_retry);
+        if will_retry {
+            debug!("on_request_error {method} hit FLOOD_WAIT_{seconds}, sleeping");
+            tokio::time::sleep(Duration::from_secs(seconds as u64)).await;
+            ControlFlow::Continue(())
+        } else {
+            error!("on_request_error {method} hit FLOOD_WAIT_{seconds}, above the threshold");
+            ControlFlow::Break(())
+        }
+    }
+}
+
+/// Send a request to Telegram through the [`RequestScheduler`] of `$backend`:
+/// `$call` is awaited (and evaluated again on retries) once the rate limit of
+/// `$method` allows it, and flood waits below the threshold are slept through.
+macro_rules! scheduled {
+    ($backend:expr, $method:expr, $call:expr) => {{
+        loop {
+            $backend.before_request($method).await;
+            match $call.await {
+                Err(e) => match $backend.on_request_error($method, &e).await {
+                    std::ops::ControlFlow::Continue(()) => continue,
+                    std::ops::ControlFlow::Break(()) => break Err(e),
+                },
+                ok => {
+                    $backend.on_request_ok();
+                    break ok;
+                }
+            }
+        }
+    }};
+}
+
+pub(crate) use scheduled;
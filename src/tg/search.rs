@@ -0,0 +1,375 @@
+use crate::tg::types::{NativeMessage, NativeSearchHit};
+use crate::tg::utils::write_atomically;
+use crate::tg::{Backend, BASE_PATH};
+use anyhow::Result;
+use const_format::concatcp;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use napi_ohos::tokio;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+pub(crate) const SEARCH_INDEX_FILE: &str = concatcp!(BASE_PATH, "search_index");
+/// Only the newest messages of each chat are kept searchable, older ones are evicted.
+const MAX_INDEXED_MESSAGES_PER_CHAT: usize = 5000;
+
+/// (chat_id, message_id)
+type DocKey = (i64, i32);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedDocument {
+    timestamp: i64,
+    text: String,
+    tokens: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexData {
+    postings: BTreeMap<String, BTreeSet<DocKey>>,
+    documents: HashMap<DocKey, IndexedDocument>,
+    /// (timestamp, message_id) of the documents of each chat, oldest first. Derived from
+    /// `documents`, so it is rebuilt on load instead of being persisted.
+    #[serde(skip)]
+    by_chat: HashMap<i64, BTreeSet<(i64, i32)>>,
+}
+
+impl IndexData {
+    fn rebuild_by_chat(&mut self) {
+        self.by_chat.clear();
+        for (key, doc) in self.documents.iter() {
+            self.by_chat.entry(key.0).or_default().insert((doc.timestamp, key.1));
+        }
+        let chat_ids: Vec<i64> = self.by_chat.keys().copied().collect();
+        for chat_id in chat_ids {
+            self.evict_oldest(chat_id);
+        }
+    }
+
+    fn insert(&mut self, key: DocKey, doc: IndexedDocument) {
+        for token in doc.tokens.iter() {
+            self.postings.entry(token.clone()).or_default().insert(key);
+        }
+        self.by_chat.entry(key.0).or_default().insert((doc.timestamp, key.1));
+        self.documents.insert(key, doc);
+        self.evict_oldest(key.0);
+    }
+
+    fn remove(&mut self, key: &DocKey) {
+        let Some(old) = self.documents.remove(key) else {
+            return;
+        };
+        for token in old.tokens.iter() {
+            if let Some(keys) = self.postings.get_mut(token) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.postings.remove(token);
+                }
+            }
+        }
+        if let Some(entries) = self.by_chat.get_mut(&key.0) {
+            entries.remove(&(old.timestamp, key.1));
+            if entries.is_empty() {
+                self.by_chat.remove(&key.0);
+            }
+        }
+    }
+
+    /// Drop the oldest documents of `chat_id` beyond [`MAX_INDEXED_MESSAGES_PER_CHAT`].
+    fn evict_oldest(&mut self, chat_id: i64) {
+        loop {
+            let oldest = match self.by_chat.get(&chat_id) {
+                Some(entries) if entries.len() > MAX_INDEXED_MESSAGES_PER_CHAT => *entries.first().unwrap(),
+                _ => return,
+            };
+            self.remove(&(chat_id, oldest.1));
+        }
+    }
+}
+
+/// An inverted index over the text of every message we have seen so far.
+///
+/// Latin-like scripts are split into lowercase words, while CJK runs (which have no
+/// word separators) are indexed as both unigrams and bigrams. Every query token is
+/// matched as a prefix, and hits are ranked by recency.
+pub(crate) struct SearchIndex {
+    data: Arc<RwLock<IndexData>>,
+    dirty: AtomicBool,
+    /// Serializes saves, so that a slower save cannot overwrite a newer one.
+    save_lock: Arc<Mutex<()>>,
+}
+
+#[inline]
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF     // Hiragana, Katakana
+        | 0x3400..=0x4DBF   // CJK Unified Ideographs Extension A
+        | 0x4E00..=0x9FFF   // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF   // Hangul Syllables
+        | 0xF900..=0xFAFF   // CJK Compatibility Ideographs
+        | 0x20000..=0x2FA1F // CJK Unified Ideographs Extension B..
+    )
+}
+
+fn flush_word(word: &mut String, tokens: &mut Vec<String>) {
+    if !word.is_empty() {
+        tokens.push(std::mem::take(word));
+    }
+}
+
+fn flush_cjk_run(run: &mut Vec<char>, tokens: &mut Vec<String>) {
+    for (i, c) in run.iter().enumerate() {
+        tokens.push(c.to_string());
+        if let Some(next) = run.get(i + 1) {
+            tokens.push([*c, *next].iter().collect());
+        }
+    }
+    run.clear();
+}
+
+/// Split `text` into index tokens, see [`SearchIndex`] for the rules.
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut cjk_run = Vec::new();
+    for c in text.chars() {
+        if is_cjk(c) {
+            flush_word(&mut word, &mut tokens);
+            cjk_run.push(c);
+        } else if c.is_alphanumeric() {
+            flush_cjk_run(&mut cjk_run, &mut tokens);
+            word.extend(c.to_lowercase());
+        } else {
+            flush_word(&mut word, &mut tokens);
+            flush_cjk_run(&mut cjk_run, &mut tokens);
+        }
+    }
+    flush_word(&mut word, &mut tokens);
+    flush_cjk_run(&mut cjk_run, &mut tokens);
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+impl SearchIndex {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: Arc::new(RwLock::new(IndexData::default())),
+            dirty: AtomicBool::new(false),
+            save_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Load the index persisted at `path`, or start with an empty one if there is none
+    /// or it cannot be decoded.
+    pub(crate) fn load_or_default(path: &str) -> Self {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                debug!("SearchIndex no index found at {path}, starting with an empty one");
+                return Self::new();
+            }
+        };
+        match bincode::serde::decode_from_slice::<IndexData, _>(&bytes, bincode::config::standard()) {
+            Ok((mut data, _)) => {
+                data.rebuild_by_chat();
+                debug!("SearchIndex loaded {} messages from {path}", data.documents.len());
+                Self {
+                    data: Arc::new(RwLock::new(data)),
+                    dirty: AtomicBool::new(false),
+                    save_lock: Arc::new(Mutex::new(())),
+                }
+            }
+            Err(e) => {
+                error!("SearchIndex failed to decode {path}: {e}, starting with an empty one");
+                Self::new()
+            }
+        }
+    }
+
+    /// Persist the index to `path` if it changed since the last save. Encoding and
+    /// writing the whole index is slow, so it happens on the blocking pool.
+    pub(crate) async fn save(&self, path: &'static str) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let data = self.data.clone();
+        let save_lock = self.save_lock.clone();
+        let result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let _guard = save_lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let bytes = {
+                let data = data.read().unwrap();
+                bincode::serde::encode_to_vec(&*data, bincode::config::standard())?
+            };
+            write_atomically(path, &bytes)?;
+            Ok(())
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.into()));
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+
+    pub(crate) fn index_message(&self, message: &NativeMessage) {
+        let key = (message.chat_id, message.message_id);
+        let tokens = tokenize(&message.text);
+        let mut data = self.data.write().unwrap();
+        data.remove(&key);
+        if !tokens.is_empty() {
+            data.insert(key, IndexedDocument {
+                timestamp: message.timestamp,
+                text: message.text.clone(),
+                tokens,
+            });
+        }
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Find the messages containing every token of `query` (each matched as a prefix),
+    /// newest first.
+    pub(crate) fn search(&self, query: &str, chat_id: Option<i64>, limit: usize) -> Vec<NativeSearchHit> {
+        let query_tokens = tokenize(query);
+        if query_tokens.is_empty() {
+            return Vec::new();
+        }
+        let data = self.data.read().unwrap();
+        let mut matched: Option<BTreeSet<DocKey>> = None;
+        for query_token in query_tokens.iter() {
+            let keys: BTreeSet<DocKey> = data
+                .postings
+                .range(query_token.clone()..)
+                .take_while(|(token, _)| token.starts_with(query_token.as_str()))
+                .flat_map(|(_, keys)| keys.iter().copied())
+                .filter(|(c, _)| chat_id.map_or(true, |chat_id| chat_id == *c))
+                .collect();
+            matched = Some(match matched {
+                Some(matched) => matched.intersection(&keys).copied().collect(),
+                None => keys,
+            });
+            if matched.as_ref().is_some_and(|m| m.is_empty()) {
+                return Vec::new();
+            }
+        }
+        let mut hits: Vec<NativeSearchHit> = matched
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|key| {
+                data.documents.get(&key).map(|doc| NativeSearchHit {
+                    chat_id: key.0,
+                    message_id: key.1,
+                    timestamp: doc.timestamp,
+                    text: doc.text.clone(),
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then(b.message_id.cmp(&a.message_id)));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+impl Backend {
+    pub fn search_messages(&self, query: &str, chat_id: Option<i64>, limit: usize) -> Vec<NativeSearchHit> {
+        debug!("search_messages query: {query}, chat_id: {chat_id:?}, limit: {limit}");
+        self.search_index.search(query, chat_id, limit)
+    }
+
+    pub(crate) async fn save_search_index(&self) {
+        match self.search_index.save(SEARCH_INDEX_FILE).await {
+            Ok(_) => debug!("save_search_index Search index saved to {SEARCH_INDEX_FILE}"),
+            Err(e) => error!("save_search_index failed to save the search index to {SEARCH_INDEX_FILE}: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(chat_id: i64, message_id: i32, timestamp: i64, text: &str) -> NativeMessage {
+        NativeMessage {
+            message_id,
+            chat_id,
+            outgoing: false,
+            pinned: false,
+            sender_id: 0,
+            sender_name: String::new(),
+            timestamp,
+            text: text.to_string(),
+            media_type: crate::tg::types::MediaType::None,
+            edit_timestamp: None,
+            grouped_id: None,
+            reply_to_message_id: None,
+            reactions: Vec::new(),
+            reply_markup: None,
+            poll: None,
+            location: None,
+            contact: None,
+            dice: None,
+            voice_note: None,
+            sticker: None,
+            animation: None,
+            web_page: None,
+        }
+    }
+
+    #[test]
+    fn tokenize_words() {
+        assert_eq!(tokenize("Hello, World! hello"), vec!["hello", "world"]);
+    }
+
+    #[test]
+    fn tokenize_cjk_unigrams_and_bigrams() {
+        assert_eq!(tokenize("你好世"), vec!["世", "你", "你好", "好", "好世"]);
+    }
+
+    #[test]
+    fn tokenize_mixed_scripts() {
+        assert_eq!(tokenize("abc你好"), vec!["abc", "你", "你好", "好"]);
+    }
+
+    #[test]
+    fn search_matches_prefixes() {
+        let index = SearchIndex::new();
+        index.index_message(&message(1, 1, 10, "telegram client"));
+        index.index_message(&message(1, 2, 20, "telescope"));
+        index.index_message(&message(2, 3, 30, "television"));
+        let hits: Vec<i32> = index.search("tele", None, 10).iter().map(|hit| hit.message_id).collect();
+        assert_eq!(hits, vec![3, 2, 1]);
+        let hits: Vec<i32> = index.search("tele", Some(1), 10).iter().map(|hit| hit.message_id).collect();
+        assert_eq!(hits, vec![2, 1]);
+        assert!(index.search("telegraph", None, 10).is_empty());
+    }
+
+    #[test]
+    fn search_matches_cjk_substrings() {
+        let index = SearchIndex::new();
+        index.index_message(&message(1, 1, 10, "我们在北京见面"));
+        assert_eq!(index.search("北京", None, 10).len(), 1);
+        assert!(index.search("上海", None, 10).is_empty());
+    }
+
+    #[test]
+    fn reindexing_replaces_the_old_text() {
+        let index = SearchIndex::new();
+        index.index_message(&message(1, 1, 10, "before"));
+        index.index_message(&message(1, 1, 10, "after"));
+        assert!(index.search("before", None, 10).is_empty());
+        assert_eq!(index.search("after", None, 10).len(), 1);
+    }
+
+    #[test]
+    fn oldest_messages_of_a_chat_are_evicted() {
+        let index = SearchIndex::new();
+        for i in 0..=MAX_INDEXED_MESSAGES_PER_CHAT as i32 {
+            index.index_message(&message(1, i, i as i64, "word"));
+        }
+        index.index_message(&message(2, 0, 0, "word"));
+        let hits = index.search("word", Some(1), usize::MAX);
+        assert_eq!(hits.len(), MAX_INDEXED_MESSAGES_PER_CHAT);
+        assert!(hits.iter().all(|hit| hit.message_id != 0));
+        assert_eq!(index.search("word", Some(2), usize::MAX).len(), 1);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::tg::utils::write_atomically;
+use crate::tg::SESSION_FILE;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::Result;
+use grammers_session::Session;
+use log::{error, info, warn};
+use std::sync::OnceLock;
+
+/// Header of an encrypted session file, followed by a format version byte.
+const SESSION_MAGIC: &[u8; 4] = b"HGSE";
+const SESSION_FORMAT_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+pub(crate) const SESSION_KEY_LEN: usize = 32;
+
+/// Supplied by the host (e.g. unwrapped from the OS keystore) before the backend starts.
+static SESSION_KEY: OnceLock<[u8; SESSION_KEY_LEN]> = OnceLock::new();
+
+pub(crate) fn set_session_key(key: &[u8]) -> Result<()> {
+    let key: [u8; SESSION_KEY_LEN] = key
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Session key must be {SESSION_KEY_LEN} bytes, got {}", key.len()))?;
+    if SESSION_KEY.set(key).is_err() && SESSION_KEY.get() != Some(&key) {
+        return Err(anyhow::anyhow!("A different session key has already been set"));
+    }
+    Ok(())
+}
+
+fn header() -> Vec<u8> {
+    let mut header = SESSION_MAGIC.to_vec();
+    header.push(SESSION_FORMAT_VERSION);
+    header
+}
+
+fn encrypt(key: &[u8; SESSION_KEY_LEN], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new(key.into());
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut nonce)?;
+    let header = header();
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &header })
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt the session"))?;
+    let mut data = header;
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+fn decrypt(key: &[u8; SESSION_KEY_LEN], data: &[u8]) -> Result<Vec<u8>> {
+    let header = header();
+    if data.len() < header.len() + NONCE_LEN || data[..header.len()] != header[..] {
+        return Err(anyhow::anyhow!("Unsupported session format"));
+    }
+    let (nonce, ciphertext) = data[header.len()..].split_at(NONCE_LEN);
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &header })
+        .map_err(|_| anyhow::anyhow!("Session authentication failed"))
+}
+
+/// Whether the file at `path` holds encrypted data.
+fn is_sealed(path: &str) -> bool {
+    use std::io::Read;
+    let mut magic = [0u8; SESSION_MAGIC.len()];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|_| &magic == SESSION_MAGIC)
+}
+
+/// Encrypt `plaintext` with the session key, if one has been set, to be written to
+/// `path`. Without a key, an encrypted file at `path` is never replaced by plaintext.
+pub(crate) fn seal(path: &str, plaintext: Vec<u8>) -> Result<Vec<u8>> {
+    match SESSION_KEY.get() {
+        Some(key) => encrypt(key, &plaintext),
+        None if is_sealed(path) => Err(anyhow::anyhow!(
+            "Refusing to overwrite the encrypted {path} with plaintext, no session key has been set"
+        )),
+        None => Ok(plaintext),
+    }
+}
+
+/// The inverse of [`seal`]. Data written before a key was set is returned as is.
+pub(crate) fn unseal(data: Vec<u8>) -> Result<Vec<u8>> {
+    if !data.starts_with(SESSION_MAGIC) {
+        return Ok(data);
+    }
+    let key = SESSION_KEY
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("The data is encrypted but no session key has been set"))?;
+    decrypt(key, &data)
+}
+
+/// Load the session from [`SESSION_FILE`].
+///
+/// A plaintext session left by an older version is migrated to an encrypted one as
+/// soon as a key is available. A session that fails to decrypt has been tampered with
+/// (or belongs to another key), so it is discarded and we start logged out. An encrypted
+/// session while no key has been set stays locked: we start logged out but leave it on
+/// disk, see [`seal`].
+pub(crate) fn load_session() -> Result<Session> {
+    let data = match std::fs::read(SESSION_FILE) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Session::new()),
+        Err(e) => return Err(e.into()),
+    };
+    if !data.starts_with(SESSION_MAGIC) {
+        let session = Session::load(&data)?;
+        if SESSION_KEY.get().is_some() {
+            info!("load_session Migrating the plaintext session to an encrypted one");
+            save_session(&session)?;
+        } else {
+            warn!("load_session No session key set, the session stays unencrypted");
+        }
+        return Ok(session);
+    }
+    let Some(key) = SESSION_KEY.get() else {
+        warn!("load_session The session is encrypted but no session key has been set, starting logged out");
+        return Ok(Session::new());
+    };
+    match decrypt(key, &data).and_then(|plaintext| Ok(Session::load(&plaintext)?)) {
+        Ok(session) => Ok(session),
+        Err(e) => {
+            error!("load_session Discarding the session: {e}");
+            std::fs::remove_file(SESSION_FILE)?;
+            Ok(Session::new())
+        }
+    }
+}
+
+/// Save `session` to [`SESSION_FILE`], encrypted if a session key has been set.
+pub(crate) fn save_session(session: &Session) -> Result<()> {
+    let data = seal(SESSION_FILE, session.save())?;
+    write_atomically(SESSION_FILE, &data)?;
+    Ok(())
+}
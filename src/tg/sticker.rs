@@ -0,0 +1,173 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{NativeDocumentRef, NativeMessage, NativeSticker, NativeStickerSet, NativeStickerSetRef};
+use crate::tg::utils::write_atomically;
+use crate::tg::{Backend, BASE_PATH};
+use anyhow::Result;
+use const_format::concatcp;
+use grammers_client::grammers_tl_types as tl;
+use grammers_mtsender::{InvocationError, RpcError};
+use log::debug;
+
+const STICKERS_DIR: &str = concatcp!(BASE_PATH, "stickers/");
+/// upload.getFile wants a multiple of 4 KiB, at most 1 MiB.
+const DOWNLOAD_CHUNK_SIZE: i32 = 512 * 1024;
+
+fn stickers_from_documents(documents: &[tl::enums::Document]) -> Vec<NativeSticker> {
+    documents
+        .iter()
+        .filter_map(|document| match document {
+            tl::enums::Document::Document(document) => NativeSticker::from_document(document),
+            tl::enums::Document::Empty(_) => None,
+        })
+        .collect()
+}
+
+impl Backend {
+    pub async fn get_installed_sticker_sets(&self) -> Result<Vec<NativeStickerSet>> {
+        let request = tl::functions::messages::GetAllStickers { hash: 0 };
+        match scheduled!(self, "messages.getAllStickers", self.client().invoke(&request))? {
+            tl::enums::messages::AllStickers::Stickers(all) => {
+                Ok(all.sets.iter().map(NativeStickerSet::from_raw).collect())
+            }
+            tl::enums::messages::AllStickers::NotModified => Ok(Vec::new()),
+        }
+    }
+
+    /// Fetch a sticker set with its stickers.
+    pub async fn get_sticker_set(&self, set: NativeStickerSetRef) -> Result<NativeStickerSet> {
+        let request = tl::functions::messages::GetStickerSet {
+            stickerset: set.to_input(),
+            hash: 0,
+        };
+        match scheduled!(self, "messages.getStickerSet", self.client().invoke(&request))? {
+            tl::enums::messages::StickerSet::Set(full) => {
+                let mut native = NativeStickerSet::from_raw(&full.set);
+                native.stickers = stickers_from_documents(&full.documents);
+                Ok(native)
+            }
+            tl::enums::messages::StickerSet::NotModified => Err(anyhow::anyhow!("Sticker set not modified")),
+        }
+    }
+
+    pub async fn install_sticker_set(&self, set: NativeStickerSetRef, archived: bool) -> Result<()> {
+        let request = tl::functions::messages::InstallStickerSet {
+            stickerset: set.to_input(),
+            archived,
+        };
+        scheduled!(self, "messages.installStickerSet", self.client().invoke(&request))?;
+        Ok(())
+    }
+
+    pub async fn uninstall_sticker_set(&self, set: NativeStickerSetRef) -> Result<()> {
+        let request = tl::functions::messages::UninstallStickerSet { stickerset: set.to_input() };
+        scheduled!(self, "messages.uninstallStickerSet", self.client().invoke(&request))?;
+        Ok(())
+    }
+
+    pub async fn get_recent_stickers(&self) -> Result<Vec<NativeSticker>> {
+        let request = tl::functions::messages::GetRecentStickers { attached: false, hash: 0 };
+        match scheduled!(self, "messages.getRecentStickers", self.client().invoke(&request))? {
+            tl::enums::messages::RecentStickers::Stickers(recent) => Ok(stickers_from_documents(&recent.stickers)),
+            tl::enums::messages::RecentStickers::NotModified => Ok(Vec::new()),
+        }
+    }
+
+    pub async fn get_favorite_stickers(&self) -> Result<Vec<NativeSticker>> {
+        let request = tl::functions::messages::GetFavedStickers { hash: 0 };
+        match scheduled!(self, "messages.getFavedStickers", self.client().invoke(&request))? {
+            tl::enums::messages::FavedStickers::Stickers(faved) => Ok(stickers_from_documents(&faved.stickers)),
+            tl::enums::messages::FavedStickers::NotModified => Ok(Vec::new()),
+        }
+    }
+
+    /// Resolve the custom emoji of message entities, to render them.
+    pub async fn get_custom_emoji_documents(&self, ids: Vec<i64>) -> Result<Vec<NativeSticker>> {
+        let request = tl::functions::messages::GetCustomEmojiDocuments { document_id: ids };
+        let documents = scheduled!(self, "messages.getCustomEmojiDocuments", self.client().invoke(&request))?;
+        Ok(stickers_from_documents(&documents))
+    }
+
+    pub async fn send_sticker(&self, chat_id: i64, document: NativeDocumentRef) -> Result<Vec<NativeMessage>> {
+        self.send_document(chat_id, &document, String::new()).await
+    }
+
+    /// A sticker with a fresh file reference, from its set or, for a custom emoji, by id.
+    async fn refresh_sticker_document(&self, sticker: &NativeSticker) -> Result<NativeDocumentRef> {
+        let id = sticker.document.id;
+        let stickers = match &sticker.set {
+            _ if sticker.custom_emoji => self.get_custom_emoji_documents(vec![id]).await?,
+            Some(set) => self.get_sticker_set(set.clone()).await?.stickers,
+            None => return Err(anyhow::anyhow!("Sticker {id} has no set to refresh its file reference from")),
+        };
+        stickers
+            .into_iter()
+            .find(|s| s.document.id == id)
+            .map(|s| s.document)
+            .ok_or_else(|| anyhow::anyhow!("Sticker {id} is gone from its set"))
+    }
+
+    /// Download the sticker (or custom emoji) file once and return its path.
+    pub async fn download_sticker(&self, sticker: NativeSticker) -> Result<String> {
+        let path = format!("{}{}.{}", STICKERS_DIR, sticker.document.id, sticker.format.extension());
+        if std::path::Path::new(&path).exists() {
+            return Ok(path);
+        }
+        std::fs::create_dir_all(STICKERS_DIR)?;
+        let mut document = sticker.document.clone();
+        let mut dc_id = document.dc_id;
+        let mut refreshed = false;
+        let mut migrated = false;
+        let mut data = Vec::with_capacity(document.size.max(0) as usize);
+        loop {
+            let request = tl::functions::upload::GetFile {
+                precise: false,
+                cdn_supported: false,
+                location: tl::types::InputDocumentFileLocation {
+                    id: document.id,
+                    access_hash: document.access_hash,
+                    file_reference: document.file_reference.clone(),
+                    thumb_size: String::new(),
+                }
+                .into(),
+                offset: data.len() as i64,
+                limit: DOWNLOAD_CHUNK_SIZE,
+            };
+            let file = self
+                .cancellable(async { scheduled!(self, "upload.getFile", self.client().invoke_in_dc(&request, dc_id)) })
+                .await;
+            let file = match file {
+                Ok(file) => file,
+                Err(e) => match e.downcast_ref::<InvocationError>() {
+                    Some(InvocationError::Rpc(RpcError { name, value: Some(dc), .. })) if name == "FILE_MIGRATE" && !migrated => {
+                        migrated = true;
+                        dc_id = *dc as i32;
+                        debug!("download_sticker {} moved to DC {dc_id}", document.id);
+                        continue;
+                    }
+                    // the file reference saved with the sticker expires after a while, fetch it again once
+                    Some(InvocationError::Rpc(rpc)) if rpc.name == "FILE_REFERENCE_EXPIRED" && !refreshed => {
+                        refreshed = true;
+                        document = self.refresh_sticker_document(&sticker).await?;
+                        dc_id = document.dc_id;
+                        // the refreshed document may live on another DC again
+                        migrated = false;
+                        debug!("download_sticker {} refreshed its file reference", document.id);
+                        continue;
+                    }
+                    _ => return Err(e),
+                },
+            };
+            let tl::enums::upload::File::File(file) = file else {
+                return Err(anyhow::anyhow!("Stickers are not served from CDNs"));
+            };
+            let len = file.bytes.len();
+            data.extend_from_slice(&file.bytes);
+            if len < DOWNLOAD_CHUNK_SIZE as usize {
+                break;
+            }
+        }
+        debug!("download_sticker Downloaded {} bytes to {path}", data.len());
+        write_atomically(&path, &data)?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,206 @@
+use crate::tg::types::{NativeChat, NativeMessage, NativeSeenChat};
+use crate::tg::utils::write_atomically;
+use crate::tg::{Backend, BASE_PATH};
+use anyhow::Result;
+use const_format::concatcp;
+use grammers_client::types::Chat;
+use grammers_session::PackedChat;
+use log::{debug, error, info};
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+
+pub(crate) const STORE_FILE: &str = concatcp!(BASE_PATH, "store");
+
+/// Bump this whenever the layout of one of the sections below changes, and append a
+/// migration to [`MIGRATIONS`] that brings the previous version up to date.
+pub(crate) const STORE_SCHEMA_VERSION: u32 = 1;
+
+/// How many of the most recent messages we keep per chat.
+const MAX_RECENT_MESSAGES_PER_CHAT: usize = 50;
+
+/// The on-disk layout of the store. Every section is encoded on its own, so that a
+/// migration only has to touch (or drop) the sections whose layout actually changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreFile {
+    version: u32,
+    /// `Vec<(chat_id, packed_chat_hex)>`
+    packed_chats: Vec<u8>,
+    /// `Vec<NativeChat>`
+    chats: Vec<u8>,
+    /// `Vec<NativeSeenChat>`
+    seen_chats: Vec<u8>,
+    /// `Vec<(chat_id, Vec<NativeMessage>)>`
+    messages: Vec<u8>,
+}
+
+type Migration = fn(StoreFile) -> Result<StoreFile>;
+
+/// `MIGRATIONS[i]` migrates a store of version `i + 1` to version `i + 2`.
+const MIGRATIONS: &[Migration] = &[];
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    Ok(bincode::serde::encode_to_vec(value, bincode::config::standard())?)
+}
+
+fn decode<T: DeserializeOwned + Default>(bytes: &[u8]) -> Result<T> {
+    if bytes.is_empty() {
+        return Ok(T::default());
+    }
+    Ok(bincode::serde::decode_from_slice(bytes, bincode::config::standard())?.0)
+}
+
+fn migrate(mut store: StoreFile) -> Result<StoreFile> {
+    if store.version > STORE_SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "store version {} is newer than the supported version {}",
+            store.version,
+            STORE_SCHEMA_VERSION
+        ));
+    }
+    while store.version < STORE_SCHEMA_VERSION {
+        let from = store.version;
+        let migration = from
+            .checked_sub(1)
+            .and_then(|i| MIGRATIONS.get(i as usize))
+            .ok_or_else(|| anyhow::anyhow!("no migration from store version {from}"))?;
+        store = migration(store)?;
+        store.version = from + 1;
+        info!("migrate Store migrated from version {} to {}", from, store.version);
+    }
+    Ok(store)
+}
+
+impl Backend {
+    /// Warm up the in-memory caches from the store persisted by the last launch.
+    pub(crate) fn load_store(&self) -> Result<()> {
+        let bytes = match std::fs::read(STORE_FILE) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                debug!("load_store No store found at {STORE_FILE}");
+                return Ok(());
+            }
+        };
+        let (store, _): (StoreFile, _) =
+            bincode::serde::decode_from_slice(&bytes, bincode::config::standard())?;
+        let store = migrate(store)?;
+
+        // every section is decoded before any is used, not to leave the caches half warm
+        let packed_chats: Vec<(i64, String)> = decode(&store.packed_chats)?;
+        let packed_chats = packed_chats
+            .into_iter()
+            .map(|(chat_id, packed_chat)| Ok((chat_id, PackedChat::from_hex(&packed_chat)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let chats: Vec<NativeChat> = decode(&store.chats)?;
+        let seen_chats: Vec<NativeSeenChat> = decode(&store.seen_chats)?;
+        let messages: Vec<(i64, Vec<NativeMessage>)> = decode(&store.messages)?;
+
+        for (chat_id, packed_chat) in packed_chats {
+            self.seen_packed_chats_map.insert(chat_id, packed_chat);
+        }
+        for chat in chats {
+            self.chats_map.insert(chat.chat_id, chat);
+        }
+        for seen_chat in seen_chats {
+            self.seen_chats_map.insert(seen_chat.chat_id, seen_chat);
+        }
+        for (chat_id, messages) in messages {
+            messages.iter().for_each(|m| self.track_poll(m));
+            self.recent_messages_map.insert(
+                chat_id,
+                messages.into_iter().map(|m| (m.message_id, m)).collect(),
+            );
+        }
+        info!(
+            "load_store Store loaded with {} chats, {} seen chats",
+            self.chats_map.len(),
+            self.seen_chats_map.len()
+        );
+        Ok(())
+    }
+
+    /// Persist the in-memory caches if they changed since the last save.
+    pub(crate) fn save_store(&self) {
+        if !self.store_dirty.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        let encoded = (|| -> Result<Vec<u8>> {
+            let packed_chats: Vec<(i64, String)> = self
+                .seen_packed_chats_map
+                .iter()
+                .map(|e| (*e.key(), e.value().to_hex()))
+                .collect();
+            let chats: Vec<NativeChat> = self.chats_map.iter().map(|e| e.value().clone()).collect();
+            let seen_chats: Vec<NativeSeenChat> =
+                self.seen_chats_map.iter().map(|e| e.value().clone()).collect();
+            let messages: Vec<(i64, Vec<NativeMessage>)> = self
+                .recent_messages_map
+                .iter()
+                .map(|e| (*e.key(), e.value().values().cloned().collect()))
+                .collect();
+            encode(&StoreFile {
+                version: STORE_SCHEMA_VERSION,
+                packed_chats: encode(&packed_chats)?,
+                chats: encode(&chats)?,
+                seen_chats: encode(&seen_chats)?,
+                messages: encode(&messages)?,
+            })
+        })();
+        match encoded.and_then(|bytes| Ok(write_atomically(STORE_FILE, &bytes)?)) {
+            Ok(_) => debug!("save_store Store saved to {STORE_FILE}"),
+            Err(e) => {
+                self.store_dirty.store(true, Ordering::Release);
+                error!("save_store failed to save the store to {STORE_FILE}: {e}");
+            }
+        }
+    }
+
+    /// Remember `chat` as a seen peer and hand it over to ArkTS.
+    pub(crate) fn cache_seen_chat(&self, chat: &Chat) -> NativeSeenChat {
+        self.seen_packed_chats_map.insert(chat.id(), chat.pack());
+        let native_seen_chat = NativeSeenChat::from_raw(chat);
+        self.seen_chats_map.insert(native_seen_chat.chat_id, native_seen_chat.clone());
+        self.store_dirty.store(true, Ordering::Release);
+        if let Some(cb) = self.cache_seen_chat_callback.as_ref() {
+            cb.call(Ok(native_seen_chat.clone()), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+        native_seen_chat
+    }
+
+    pub(crate) fn cache_chat(&self, chat: &NativeChat) {
+        self.chats_map.insert(chat.chat_id, chat.clone());
+        self.store_dirty.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn cache_recent_message(&self, message: &NativeMessage) {
+        self.track_poll(message);
+        let mut messages = self.recent_messages_map.entry(message.chat_id).or_insert_with(BTreeMap::new);
+        messages.insert(message.message_id, message.clone());
+        while messages.len() > MAX_RECENT_MESSAGES_PER_CHAT {
+            // updateMessagePoll of an evicted message has nothing left to apply to
+            if let Some((_, evicted)) = messages.pop_first() {
+                if let Some(poll) = evicted.poll.as_ref() {
+                    self.poll_messages.remove(&poll.poll_id);
+                }
+            }
+        }
+        self.store_dirty.store(true, Ordering::Release);
+    }
+
+    pub fn get_cached_chats(&self) -> Vec<NativeChat> {
+        self.chats_map.iter().map(|e| e.value().clone()).collect()
+    }
+
+    pub fn get_cached_seen_chats(&self) -> Vec<NativeSeenChat> {
+        self.seen_chats_map.iter().map(|e| e.value().clone()).collect()
+    }
+
+    pub fn get_cached_messages(&self, chat_id: i64) -> Vec<NativeMessage> {
+        self.recent_messages_map
+            .get(&chat_id)
+            .map(|messages| messages.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
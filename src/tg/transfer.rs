@@ -0,0 +1,150 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{ChatActionKind, NativeChatAction, UpdateUploadProgressCallback};
+use crate::tg::typing::CHAT_ACTION_REPEAT;
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::types::media::Uploaded;
+use log::{debug, error};
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use napi_ohos::tokio;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Wraps the stream being uploaded and reports the progress in whole percents.
+struct ProgressReader<R> {
+    inner: R,
+    read: usize,
+    len: usize,
+    index: i64,
+    last_progress: i64,
+    callback: Option<Arc<UpdateUploadProgressCallback>>,
+    /// the same progress, for the upload chat action
+    shared_progress: Arc<AtomicI32>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.read += buf.filled().len() - filled;
+            let progress = (self.read as f64 / self.len.max(1) as f64 * 100f64) as i64;
+            if progress != self.last_progress {
+                self.last_progress = progress;
+                self.shared_progress.store(progress as i32, Ordering::Relaxed);
+                if let Some(callback) = self.callback.as_ref() {
+                    callback.call(Ok((self.index, progress)), ThreadsafeFunctionCallMode::NonBlocking);
+                }
+            }
+        }
+        poll
+    }
+}
+
+/// The error of a transfer cut short by [`Backend::shutdown`]. It says nothing about
+/// the transfer itself, which can be retried as is.
+#[derive(Debug)]
+pub(crate) struct TransferCancelled;
+
+impl std::fmt::Display for TransferCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Transfer cancelled")
+    }
+}
+
+impl std::error::Error for TransferCancelled {}
+
+impl ProgressReader<std::io::Cursor<Vec<u8>>> {
+    /// Start over, for when the upload is retried.
+    fn rewind(&mut self) {
+        self.inner.set_position(0);
+        self.read = 0;
+    }
+}
+
+impl Backend {
+    /// Run the transfer `fut` until it completes or the transfers get cancelled by
+    /// [`Backend::shutdown`].
+    pub(crate) async fn cancellable<T, E: Into<anyhow::Error>>(
+        &self,
+        fut: impl Future<Output = std::result::Result<T, E>>,
+    ) -> Result<T> {
+        let token = self.transfer_token.lock().unwrap().clone();
+        tokio::select! {
+            _ = token.cancelled() => Err(TransferCancelled.into()),
+            result = fut => result.map_err(Into::into),
+        }
+    }
+
+    /// Upload the file at `path`, reporting the progress as the `index`-th media of the
+    /// message being sent. With `action`, the others in the chat see it as that chat action.
+    pub(crate) async fn upload_file(
+        &self,
+        path: &str,
+        index: usize,
+        update_upload_progress_callback: Option<Arc<UpdateUploadProgressCallback>>,
+        action: Option<(i64, ChatActionKind)>,
+    ) -> Result<Uploaded> {
+        let raw_file = std::fs::read(path)?;
+        let len = raw_file.len();
+        let mut stream = ProgressReader {
+            inner: std::io::Cursor::new(raw_file),
+            read: 0,
+            len,
+            index: index as i64,
+            last_progress: -1,
+            callback: update_upload_progress_callback,
+            shared_progress: Arc::new(AtomicI32::new(0)),
+        };
+        let file_name = std::path::Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("file")
+            .to_string();
+        debug!("upload_file Uploading {} ({} bytes)", path, len);
+        let progress = stream.shared_progress.clone();
+        let client = self.client();
+        let upload = self.cancellable(async {
+            scheduled!(self, "upload.saveFilePart", {
+                stream.rewind();
+                client.upload_stream(&mut stream, len, file_name.clone())
+            })
+        });
+        let result = match action {
+            Some((chat_id, kind)) => {
+                tokio::pin!(upload);
+                // the action expires unless repeated
+                let result = loop {
+                    let report = async {
+                        self.send_upload_action(chat_id, kind, progress.load(Ordering::Relaxed)).await;
+                        tokio::time::sleep(CHAT_ACTION_REPEAT).await;
+                    };
+                    tokio::select! {
+                        result = &mut upload => break result,
+                        _ = report => {}
+                    }
+                };
+                if result.is_err() {
+                    let cancel = NativeChatAction {
+                        kind: ChatActionKind::Cancel,
+                        progress: None,
+                    };
+                    let _ = self.send_chat_action(chat_id, cancel).await;
+                }
+                result
+            }
+            None => upload.await,
+        };
+        match result {
+            Ok(uploaded) => Ok(uploaded),
+            Err(e) => {
+                error!("Failed to upload media: {e}");
+                Err(e)
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::tg::utils::peer_id;
 use grammers_client::grammers_tl_types as tl;
 use grammers_client::types::Chat;
 use napi_derive_ohos::napi;
@@ -13,10 +14,28 @@ pub type UpdateChatCallback = ThreadsafeFunction<(
     NativeChat,
     Vec<NativeMessage>,
 ), Promise<()>>;
-pub type IncomingMessageCallback = ThreadsafeFunction<(Option<NativeChat>, NativeMessage)>;
+// (new_chat, message, is_live): historical messages received while catching up are not live
+pub type IncomingMessageCallback = ThreadsafeFunction<(Option<NativeChat>, NativeMessage, bool)>;
 
 // (media_index, current_progress): void => {} 
 pub type UpdateUploadProgressCallback = ThreadsafeFunction<(i64, i64), Promise<()>>;
+pub type OutboxCallback = ThreadsafeFunction<NativeOutboxMessage>;
+pub type ThrottleCallback = ThreadsafeFunction<NativeThrottleEvent>;
+pub type ConnectionStateCallback = ThreadsafeFunction<NativeConnectionState>;
+pub type LifecycleStateCallback = ThreadsafeFunction<LifecycleState>;
+pub type CatchUpCallback = ThreadsafeFunction<NativeCatchUpEvent>;
+// (chat_id, message_id, reactions)
+pub type MessageReactionsCallback = ThreadsafeFunction<(i64, i32, Vec<NativeReactionCount>)>;
+// (chat_id, message_id, poll)
+pub type PollCallback = ThreadsafeFunction<(i64, i32, NativePoll)>;
+// (chat_id, notify_settings)
+pub type ChatNotifySettingsCallback = ThreadsafeFunction<(i64, NativePeerNotifySettings)>;
+// (chat_id, draft), no draft once it is cleared or sent
+pub type ChatDraftCallback = ThreadsafeFunction<(i64, Option<NativeDraft>)>;
+// (chat_id, scheduled messages added or edited, ids of the ones deleted or sent)
+pub type ScheduledMessagesCallback = ThreadsafeFunction<(i64, Vec<NativeMessage>, Vec<i32>)>;
+// (chat_id, who is typing there now), an empty list once everyone stopped
+pub type ChatTypingCallback = ThreadsafeFunction<(i64, Vec<NativeTypingUser>)>;
 #[derive(Debug, PartialEq)]
 #[napi]
 pub enum LoginState {
@@ -43,6 +62,28 @@ pub enum MediaType {
     Venue,
     GeoLive,
     WebPage,
+    Voice,
+    VideoNote,
+    Animation,
+}
+
+impl From<Option<&tl::enums::MessageMedia>> for MediaType {
+    /// Stickers are documents as well, the caller tells them apart.
+    fn from(value: Option<&tl::enums::MessageMedia>) -> Self {
+        use tl::enums::MessageMedia;
+        match value {
+            Some(MessageMedia::Photo(photo)) if photo.photo.is_some() => MediaType::Photo,
+            Some(MessageMedia::Document(document)) if document.document.is_some() => MediaType::Document,
+            Some(MessageMedia::Contact(_)) => MediaType::Contact,
+            Some(MessageMedia::Poll(_)) => MediaType::Poll,
+            Some(MessageMedia::Geo(_)) => MediaType::Geo,
+            Some(MessageMedia::Dice(_)) => MediaType::Dice,
+            Some(MessageMedia::Venue(_)) => MediaType::Venue,
+            Some(MessageMedia::GeoLive(_)) => MediaType::GeoLive,
+            Some(MessageMedia::WebPage(_)) => MediaType::WebPage,
+            _ => MediaType::None,
+        }
+    }
 }
 
 impl From<Option<grammers_client::types::Media>> for MediaType {
@@ -65,6 +106,106 @@ impl From<Option<grammers_client::types::Media>> for MediaType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[napi]
+pub enum OutboxState {
+    Pending,
+    Sending,
+    Sent,
+    Failed,
+}
+
+/// A message waiting in the outbox. `temp_id` is a local, negative id that is mapped to
+/// the real `message_id`s through `messages` once the message is delivered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeOutboxMessage {
+    pub temp_id: i64,
+    pub chat_id: i64,
+    pub text: String,
+    pub medias: Vec<String>,
+    pub created_at: i64,
+    pub attempts: u32,
+    pub next_attempt_at: i64,
+    pub state: OutboxState,
+    pub last_error: Option<String>,
+    pub messages: Vec<NativeMessage>,
+    pub options: NativeSendOptions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[napi]
+pub enum LifecycleState {
+    Stopped,
+    Running,
+    /// the app is in the background, updates are not handled
+    Paused,
+    ShuttingDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[napi]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// connected, fetching the updates we missed while offline
+    Updating,
+    WaitingForNetwork,
+    BackingOff,
+    /// gave up reconnecting after the configured attempts, see `restart_connection`
+    Disconnected,
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeConnectionState {
+    pub state: ConnectionState,
+    pub attempt: u32,
+    /// when the next attempt happens if backing off, -1 if unknown
+    pub retry_in_ms: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[napi]
+pub enum ProxyType {
+    Socks5,
+    Http,
+    MtProxy,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeProxyConfig {
+    pub proxy_type: ProxyType,
+    pub host: String,
+    pub port: u32,
+    /// SOCKS5 and HTTP only, at most 255 bytes
+    pub username: Option<String>,
+    /// SOCKS5 and HTTP only, at most 255 bytes
+    pub password: Option<String>,
+    /// MTProxy only, hex or base64url encoded as in `tg://proxy` links
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeProxyTestResult {
+    pub reachable: bool,
+    /// -1 if not reachable
+    pub latency_ms: i64,
+    pub error: Option<String>,
+}
+
+/// Reported whenever Telegram asks us to slow down on `method`.
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeThrottleEvent {
+    pub method: String,
+    pub wait_seconds: u32,
+    /// false if the wait is above the flood sleep threshold and the request failed
+    pub will_retry: bool,
+}
+
 #[derive(Clone)]
 #[napi(object)]
 pub struct NativePackedChat {
@@ -80,6 +221,15 @@ pub struct NativeRawMessage {
     pub raw_message: Buffer,
 }
 
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeSearchHit {
+    pub chat_id: i64,
+    pub message_id: i32,
+    pub timestamp: i64,
+    pub text: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[napi(object)]
 pub struct NativeMessage {
@@ -95,6 +245,16 @@ pub struct NativeMessage {
     pub edit_timestamp: Option<i64>,
     pub grouped_id: Option<i64>,
     pub reply_to_message_id: Option<i32>,
+    pub reactions: Vec<NativeReactionCount>,
+    pub reply_markup: Option<NativeReplyMarkup>,
+    pub poll: Option<NativePoll>,
+    pub location: Option<NativeLocation>,
+    pub contact: Option<NativeContact>,
+    pub dice: Option<NativeDice>,
+    pub voice_note: Option<NativeVoiceNote>,
+    pub sticker: Option<NativeSticker>,
+    pub animation: Option<NativeAnimation>,
+    pub web_page: Option<NativeWebPage>,
 }
 
 impl NativeMessage {
@@ -105,19 +265,84 @@ impl NativeMessage {
             sender_id = raw.sender().unwrap().id();
             sender_name = raw.sender().unwrap().name().to_string();
         }
+        Self::from_tl(&raw.raw, sender_id, sender_name)
+    }
+
+    /// For the messages grammers doesn't wrap, e.g. scheduled ones.
+    pub fn from_tl(raw: &tl::types::Message, sender_id: i64, sender_name: String) -> Self {
+        let voice_note = raw.media.as_ref().and_then(NativeVoiceNote::from_media);
+        let animation = match &raw.media {
+            Some(tl::enums::MessageMedia::Document(tl::types::MessageMediaDocument {
+                document: Some(tl::enums::Document::Document(document)),
+                ..
+            })) => NativeAnimation::from_document(document),
+            _ => None,
+        };
+        let sticker = match &raw.media {
+            Some(tl::enums::MessageMedia::Document(tl::types::MessageMediaDocument {
+                document: Some(tl::enums::Document::Document(document)),
+                ..
+            })) => NativeSticker::from_document(document),
+            _ => None,
+        };
         Self {
-            message_id: raw.id(),
-            chat_id: raw.chat().id(),
-            outgoing: raw.outgoing(),
-            pinned: raw.pinned(),
+            message_id: raw.id,
+            chat_id: peer_id(&raw.peer_id),
+            outgoing: raw.out,
+            pinned: raw.pinned,
             sender_id,
             sender_name,
-            timestamp: raw.date().timestamp(),
-            text: raw.text().to_string(),
-            media_type: MediaType::from(raw.media()),
-            edit_timestamp: raw.edit_date().map(|d| d.timestamp()),
-            grouped_id: raw.grouped_id(),
-            reply_to_message_id: raw.reply_to_message_id(),
+            timestamp: raw.date as i64,
+            text: raw.message.clone(),
+            media_type: match voice_note.as_ref() {
+                Some(voice_note) if voice_note.video => MediaType::VideoNote,
+                Some(_) => MediaType::Voice,
+                None if animation.is_some() => MediaType::Animation,
+                None if sticker.is_some() => MediaType::Sticker,
+                None => MediaType::from(raw.media.as_ref()),
+            },
+            edit_timestamp: raw.edit_date.map(|d| d as i64),
+            grouped_id: raw.grouped_id,
+            reply_to_message_id: match &raw.reply_to {
+                Some(tl::enums::MessageReplyHeader::Header(header)) => header.reply_to_msg_id,
+                _ => None,
+            },
+            reactions: raw.reactions.as_ref().map(NativeReactionCount::from_reactions).unwrap_or_default(),
+            reply_markup: raw.reply_markup.as_ref().map(NativeReplyMarkup::from_raw),
+            poll: match &raw.media {
+                Some(tl::enums::MessageMedia::Poll(media)) => Some(NativePoll::from_raw(&media.poll, &media.results)),
+                _ => None,
+            },
+            location: raw.media.as_ref().and_then(NativeLocation::from_media),
+            contact: match &raw.media {
+                Some(tl::enums::MessageMedia::Contact(contact)) => Some(NativeContact {
+                    phone_number: contact.phone_number.clone(),
+                    first_name: contact.first_name.clone(),
+                    last_name: contact.last_name.clone(),
+                    vcard: contact.vcard.clone(),
+                    user_id: (contact.user_id != 0).then_some(contact.user_id),
+                }),
+                _ => None,
+            },
+            dice: match &raw.media {
+                Some(tl::enums::MessageMedia::Dice(dice)) => Some(NativeDice {
+                    emoticon: dice.emoticon.clone(),
+                    value: dice.value,
+                }),
+                _ => None,
+            },
+            voice_note,
+            sticker,
+            animation,
+            web_page: match &raw.media {
+                Some(tl::enums::MessageMedia::WebPage(media)) => {
+                    NativeWebPage::from_media(media).map(|page| NativeWebPage {
+                        above_text: raw.invert_media,
+                        ..page
+                    })
+                }
+                _ => None,
+            },
         }
     }
 }
@@ -190,6 +415,8 @@ pub struct NativeChat {
     pub last_message_timestamp: i64,
     pub megagroup: bool,
     pub forum: bool,
+    pub notify_settings: NativePeerNotifySettings,
+    pub draft: Option<NativeDraft>,
     // pub forums: Option<Vec<i64>>,
 }
 
@@ -221,6 +448,8 @@ impl NativeChat {
             last_message_timestamp: 0,
             megagroup,
             forum,
+            notify_settings: NativePeerNotifySettings::default(),
+            draft: None,
         }
     }
 
@@ -259,6 +488,8 @@ impl NativeChat {
             last_message_timestamp,
             megagroup,
             forum,
+            notify_settings: NativePeerNotifySettings::from_dialog(&dialog.raw),
+            draft: NativeDraft::from_dialog(&dialog.raw),
         }
     }
 }
@@ -347,4 +578,1211 @@ impl NativeSeenChat {
             forum: raw.raw.forum,
         }
     }
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[napi]
+pub enum CatchUpState {
+    Started,
+    Progress,
+    Finished,
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeCatchUpEvent {
+    pub state: CatchUpState,
+    /// Historical updates handled so far.
+    pub processed: u32,
+    /// How old the last historical update was, 0 when unknown.
+    pub behind_seconds: i64,
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeDifferenceSummary {
+    pub new_messages: u32,
+    pub other_updates: u32,
+    /// How old the oldest missed message was.
+    pub behind_seconds: i64,
+    /// The gap was too large to fill, the chats should be reloaded instead.
+    pub too_long: bool,
+}
+
+/// A push notification decrypted by `decrypt_push_payload`.
+/// https://core.telegram.org/api/push-updates#notification-types
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativePushNotification {
+    /// e.g. `MESSAGE_TEXT`, `CHAT_MESSAGE_PHOTO`; empty for silent service pushes.
+    pub loc_key: String,
+    pub loc_args: Vec<String>,
+    pub chat_id: Option<i64>,
+    pub message_id: Option<i32>,
+    pub sender_name: Option<String>,
+    pub preview_text: Option<String>,
+    pub silent: bool,
+    pub mute: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[napi]
+pub enum NotificationSound {
+    Default,
+    /// no sound at all
+    None,
+    /// one of the ringtones uploaded to the account, see `ringtone_id`
+    Ringtone,
+}
+
+/// The notification settings of a chat. `None` means "use the default of the scope".
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativePeerNotifySettings {
+    pub show_previews: Option<bool>,
+    pub silent: Option<bool>,
+    /// unix time until which the chat is muted, `i32::MAX` for "forever"
+    pub mute_until: Option<i32>,
+    pub sound: Option<NotificationSound>,
+    pub ringtone_id: Option<i64>,
+}
+
+impl NativePeerNotifySettings {
+    pub fn from_raw(raw: &tl::enums::PeerNotifySettings) -> Self {
+        let tl::enums::PeerNotifySettings::Settings(raw) = raw;
+        let (sound, ringtone_id) = match raw.other_sound.as_ref().or(raw.android_sound.as_ref()) {
+            Some(tl::enums::NotificationSound::Default) => (Some(NotificationSound::Default), None),
+            Some(tl::enums::NotificationSound::None) => (Some(NotificationSound::None), None),
+            Some(tl::enums::NotificationSound::Ringtone(r)) => (Some(NotificationSound::Ringtone), Some(r.id)),
+            // sounds of other apps don't mean anything to us
+            Some(tl::enums::NotificationSound::Local(_)) | None => (None, None),
+        };
+        Self {
+            show_previews: raw.show_previews,
+            silent: raw.silent,
+            mute_until: raw.mute_until,
+            sound,
+            ringtone_id,
+        }
+    }
+
+    pub fn from_dialog(raw: &tl::enums::Dialog) -> Self {
+        match raw {
+            tl::enums::Dialog::Dialog(dialog) => Self::from_raw(&dialog.notify_settings),
+            tl::enums::Dialog::Folder(_) => Self::default(),
+        }
+    }
+
+    pub fn to_input(&self) -> tl::enums::InputPeerNotifySettings {
+        let sound = match (self.sound, self.ringtone_id) {
+            (Some(NotificationSound::Default), _) => Some(tl::enums::NotificationSound::Default),
+            (Some(NotificationSound::None), _) => Some(tl::enums::NotificationSound::None),
+            (Some(NotificationSound::Ringtone), Some(id)) => {
+                Some(tl::types::NotificationSoundRingtone { id }.into())
+            }
+            _ => None,
+        };
+        tl::types::InputPeerNotifySettings {
+            show_previews: self.show_previews,
+            silent: self.silent,
+            mute_until: self.mute_until,
+            sound,
+            stories_muted: None,
+            stories_hide_sender: None,
+            stories_sound: None,
+        }
+        .into()
+    }
+
+    /// Whether the chat is muted at unix time `now`.
+    pub fn is_muted_at(&self, now: i64) -> bool {
+        self.mute_until.is_some_and(|until| until as i64 > now)
+    }
+}
+
+/// Either a plain emoji or a custom emoji, as in `Reaction`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeReaction {
+    pub emoticon: Option<String>,
+    pub custom_emoji_id: Option<i64>,
+}
+
+impl NativeReaction {
+    pub fn from_raw(raw: &tl::enums::Reaction) -> Option<Self> {
+        match raw {
+            tl::enums::Reaction::Emoji(emoji) => Some(Self {
+                emoticon: Some(emoji.emoticon.clone()),
+                custom_emoji_id: None,
+            }),
+            tl::enums::Reaction::CustomEmoji(emoji) => Some(Self {
+                emoticon: None,
+                custom_emoji_id: Some(emoji.document_id),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn to_raw(&self) -> tl::enums::Reaction {
+        match (self.custom_emoji_id, self.emoticon.as_ref()) {
+            (Some(document_id), _) => tl::types::ReactionCustomEmoji { document_id }.into(),
+            (None, Some(emoticon)) => tl::types::ReactionEmoji { emoticon: emoticon.clone() }.into(),
+            (None, None) => tl::enums::Reaction::Empty,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeReactionCount {
+    pub reaction: NativeReaction,
+    pub count: i32,
+    /// whether I reacted with it
+    pub chosen: bool,
+}
+
+impl NativeReactionCount {
+    pub fn from_reactions(raw: &tl::enums::MessageReactions) -> Vec<Self> {
+        let tl::enums::MessageReactions::Reactions(raw) = raw;
+        raw.results
+            .iter()
+            .filter_map(|tl::enums::ReactionCount::Count(count)| {
+                Some(Self {
+                    reaction: NativeReaction::from_raw(&count.reaction)?,
+                    count: count.count,
+                    chosen: count.chosen_order.is_some(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The reactions allowed in a chat.
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeAvailableReactions {
+    /// every standard emoji is allowed, `reactions` is empty
+    pub all: bool,
+    pub allow_custom: bool,
+    pub reactions: Vec<NativeReaction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[napi]
+pub enum ReplyMarkupKind {
+    /// buttons attached to the message
+    Inline,
+    /// a custom keyboard replacing the system one
+    Keyboard,
+    /// remove the custom keyboard
+    Hide,
+    /// open the reply UI
+    ForceReply,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[napi]
+pub enum KeyboardButtonKind {
+    /// sends its text as a message
+    Text,
+    Url,
+    Callback,
+    SwitchInline,
+    RequestPhone,
+    RequestGeoLocation,
+    RequestPoll,
+    Game,
+    Buy,
+    UrlAuth,
+    WebView,
+    UserProfile,
+    /// a button we don't support yet
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeKeyboardButton {
+    pub kind: KeyboardButtonKind,
+    pub text: String,
+    pub url: Option<String>,
+    /// the payload to give to `press_button`
+    pub data: Option<Vec<u8>>,
+    /// the query to insert for `SwitchInline`
+    pub query: Option<String>,
+    /// `SwitchInline` in the current chat instead of picking one
+    pub same_peer: bool,
+    pub requires_password: bool,
+    pub user_id: Option<i64>,
+}
+
+impl NativeKeyboardButton {
+    fn new(kind: KeyboardButtonKind, text: &str) -> Self {
+        Self {
+            kind,
+            text: text.to_string(),
+            url: None,
+            data: None,
+            query: None,
+            same_peer: false,
+            requires_password: false,
+            user_id: None,
+        }
+    }
+
+    pub fn from_raw(raw: &tl::enums::KeyboardButton) -> Self {
+        use tl::enums::KeyboardButton as B;
+        use KeyboardButtonKind as K;
+        match raw {
+            B::Button(b) => Self::new(K::Text, &b.text),
+            B::Url(b) => Self { url: Some(b.url.clone()), ..Self::new(K::Url, &b.text) },
+            B::Callback(b) => Self {
+                data: Some(b.data.clone()),
+                requires_password: b.requires_password,
+                ..Self::new(K::Callback, &b.text)
+            },
+            B::SwitchInline(b) => Self {
+                query: Some(b.query.clone()),
+                same_peer: b.same_peer,
+                ..Self::new(K::SwitchInline, &b.text)
+            },
+            B::RequestPhone(b) => Self::new(K::RequestPhone, &b.text),
+            B::RequestGeoLocation(b) => Self::new(K::RequestGeoLocation, &b.text),
+            B::RequestPoll(b) => Self::new(K::RequestPoll, &b.text),
+            B::Game(b) => Self::new(K::Game, &b.text),
+            B::Buy(b) => Self::new(K::Buy, &b.text),
+            B::UrlAuth(b) => Self { url: Some(b.url.clone()), ..Self::new(K::UrlAuth, &b.text) },
+            B::WebView(b) => Self { url: Some(b.url.clone()), ..Self::new(K::WebView, &b.text) },
+            B::SimpleWebView(b) => Self { url: Some(b.url.clone()), ..Self::new(K::WebView, &b.text) },
+            B::UserProfile(b) => Self { user_id: Some(b.user_id), ..Self::new(K::UserProfile, &b.text) },
+            B::RequestPeer(b) => Self::new(K::Other, &b.text),
+            // the input variants are only sent by bots, but are kept as they are parsed
+            B::InputKeyboardButtonUrlAuth(b) => Self::new(K::Other, &b.text),
+            B::InputKeyboardButtonUserProfile(b) => Self::new(K::Other, &b.text),
+            B::InputKeyboardButtonRequestPeer(b) => Self::new(K::Other, &b.text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeReplyMarkup {
+    pub kind: ReplyMarkupKind,
+    pub rows: Vec<Vec<NativeKeyboardButton>>,
+    pub resize: bool,
+    pub single_use: bool,
+    pub selective: bool,
+    pub placeholder: Option<String>,
+}
+
+impl NativeReplyMarkup {
+    fn rows_from_raw(rows: &[tl::enums::KeyboardButtonRow]) -> Vec<Vec<NativeKeyboardButton>> {
+        rows.iter()
+            .map(|tl::enums::KeyboardButtonRow::Row(row)| {
+                row.buttons.iter().map(NativeKeyboardButton::from_raw).collect()
+            })
+            .collect()
+    }
+
+    pub fn from_raw(raw: &tl::enums::ReplyMarkup) -> Self {
+        let mut markup = Self {
+            kind: ReplyMarkupKind::Inline,
+            rows: Vec::new(),
+            resize: false,
+            single_use: false,
+            selective: false,
+            placeholder: None,
+        };
+        match raw {
+            tl::enums::ReplyMarkup::ReplyInlineMarkup(inline) => {
+                markup.rows = Self::rows_from_raw(&inline.rows);
+            }
+            tl::enums::ReplyMarkup::ReplyKeyboardMarkup(keyboard) => {
+                markup.kind = ReplyMarkupKind::Keyboard;
+                markup.rows = Self::rows_from_raw(&keyboard.rows);
+                markup.resize = keyboard.resize;
+                markup.single_use = keyboard.single_use;
+                markup.selective = keyboard.selective;
+                markup.placeholder = keyboard.placeholder.clone();
+            }
+            tl::enums::ReplyMarkup::ReplyKeyboardHide(hide) => {
+                markup.kind = ReplyMarkupKind::Hide;
+                markup.selective = hide.selective;
+            }
+            tl::enums::ReplyMarkup::ReplyKeyboardForceReply(force_reply) => {
+                markup.kind = ReplyMarkupKind::ForceReply;
+                markup.single_use = force_reply.single_use;
+                markup.selective = force_reply.selective;
+                markup.placeholder = force_reply.placeholder.clone();
+            }
+        }
+        markup
+    }
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeBotCallbackAnswer {
+    /// the text to show, as an alert if `alert` is set or as a toast otherwise
+    pub message: Option<String>,
+    pub alert: bool,
+    /// a URL to open, e.g. to start a game
+    pub url: Option<String>,
+    /// how long the answer may be reused for the same button, in seconds
+    pub cache_time: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[napi]
+pub enum InlineResultType {
+    Article,
+    Photo,
+    Gif,
+    Sticker,
+    Video,
+    Audio,
+    Voice,
+    Document,
+    Location,
+    Venue,
+    Contact,
+    Game,
+    Other,
+}
+
+impl InlineResultType {
+    /// https://core.telegram.org/bots/api#inlinequeryresult
+    pub fn from_type(raw: &str) -> Self {
+        match raw {
+            "article" => Self::Article,
+            "photo" => Self::Photo,
+            "gif" | "mpeg4_gif" => Self::Gif,
+            "sticker" => Self::Sticker,
+            "video" => Self::Video,
+            "audio" => Self::Audio,
+            "voice" => Self::Voice,
+            "file" => Self::Document,
+            "geo" | "location" => Self::Location,
+            "venue" => Self::Venue,
+            "contact" => Self::Contact,
+            "game" => Self::Game,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeInlineResult {
+    /// the id to give to `send_inline_result`
+    pub id: String,
+    pub result_type: InlineResultType,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub thumb_url: Option<String>,
+    pub content_url: Option<String>,
+    /// a tiny inline preview, for results carrying a photo or a document
+    pub stripped_thumb: Option<Vec<u8>>,
+}
+
+fn web_document_url(raw: &tl::enums::WebDocument) -> String {
+    match raw {
+        tl::enums::WebDocument::Document(document) => document.url.clone(),
+        tl::enums::WebDocument::NoProxy(document) => document.url.clone(),
+    }
+}
+
+pub(crate) fn stripped_thumb(sizes: &[tl::enums::PhotoSize]) -> Option<Vec<u8>> {
+    sizes.iter().find_map(|size| match size {
+        tl::enums::PhotoSize::PhotoStrippedSize(size) => Some(size.bytes.clone()),
+        _ => None,
+    })
+}
+
+impl NativeInlineResult {
+    pub fn from_raw(raw: &tl::enums::BotInlineResult) -> Self {
+        match raw {
+            tl::enums::BotInlineResult::Result(result) => Self {
+                id: result.id.clone(),
+                result_type: InlineResultType::from_type(&result.r#type),
+                title: result.title.clone(),
+                description: result.description.clone(),
+                url: result.url.clone(),
+                thumb_url: result.thumb.as_ref().map(web_document_url),
+                content_url: result.content.as_ref().map(web_document_url),
+                stripped_thumb: None,
+            },
+            tl::enums::BotInlineResult::BotInlineMediaResult(result) => {
+                let stripped_thumb = match (&result.photo, &result.document) {
+                    (Some(tl::enums::Photo::Photo(photo)), _) => stripped_thumb(&photo.sizes),
+                    (_, Some(tl::enums::Document::Document(document))) => {
+                        document.thumbs.as_deref().and_then(stripped_thumb)
+                    }
+                    _ => None,
+                };
+                Self {
+                    id: result.id.clone(),
+                    result_type: InlineResultType::from_type(&result.r#type),
+                    title: result.title.clone(),
+                    description: result.description.clone(),
+                    url: None,
+                    thumb_url: None,
+                    content_url: None,
+                    stripped_thumb,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeInlineResults {
+    pub query_id: i64,
+    /// the offset to ask for the next page with, `None` once there is no more
+    pub next_offset: Option<String>,
+    /// show the results as a grid of media rather than a list
+    pub gallery: bool,
+    pub cache_time: i32,
+    pub results: Vec<NativeInlineResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativePollAnswer {
+    pub text: String,
+    /// the opaque id of the answer in votes
+    pub option: Vec<u8>,
+    /// `None` until I voted (or the poll is closed)
+    pub voters: Option<i32>,
+    /// whether I voted for it
+    pub chosen: bool,
+    /// the right answer of a quiz, known once I voted
+    pub correct: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativePoll {
+    pub poll_id: i64,
+    pub question: String,
+    pub answers: Vec<NativePollAnswer>,
+    pub closed: bool,
+    pub public_voters: bool,
+    pub multiple_choice: bool,
+    pub quiz: bool,
+    pub close_period: Option<i32>,
+    pub close_date: Option<i32>,
+    pub total_voters: Option<i32>,
+    /// the explanation of a quiz, shown after voting
+    pub solution: Option<String>,
+}
+
+fn text_with_entities(raw: &tl::enums::TextWithEntities) -> String {
+    let tl::enums::TextWithEntities::Entities(raw) = raw;
+    raw.text.clone()
+}
+
+impl NativePoll {
+    pub fn from_raw(poll: &tl::enums::Poll, results: &tl::enums::PollResults) -> Self {
+        let tl::enums::Poll::Poll(poll) = poll;
+        let mut native = Self {
+            poll_id: poll.id,
+            question: text_with_entities(&poll.question),
+            answers: poll
+                .answers
+                .iter()
+                .map(|tl::enums::PollAnswer::Answer(answer)| NativePollAnswer {
+                    text: text_with_entities(&answer.text),
+                    option: answer.option.clone(),
+                    voters: None,
+                    chosen: false,
+                    correct: false,
+                })
+                .collect(),
+            closed: poll.closed,
+            public_voters: poll.public_voters,
+            multiple_choice: poll.multiple_choice,
+            quiz: poll.quiz,
+            close_period: poll.close_period,
+            close_date: poll.close_date,
+            total_voters: None,
+            solution: None,
+        };
+        native.apply_results(results);
+        native
+    }
+
+    /// Merge the results of an update. `min` results don't know which answers I chose,
+    /// so those are kept.
+    pub fn apply_results(&mut self, results: &tl::enums::PollResults) {
+        let tl::enums::PollResults::Results(results) = results;
+        if let Some(voters) = results.results.as_ref() {
+            for tl::enums::PollAnswerVoters::Voters(voters) in voters {
+                if let Some(answer) = self.answers.iter_mut().find(|a| a.option == voters.option) {
+                    answer.voters = Some(voters.voters);
+                    if !results.min {
+                        answer.chosen = voters.chosen;
+                        answer.correct = voters.correct;
+                    }
+                }
+            }
+        }
+        if results.total_voters.is_some() {
+            self.total_voters = results.total_voters;
+        }
+        if results.solution.is_some() {
+            self.solution = results.solution.clone();
+        }
+    }
+
+    pub fn to_raw(&self) -> tl::enums::Poll {
+        tl::types::Poll {
+            id: self.poll_id,
+            closed: self.closed,
+            public_voters: self.public_voters,
+            multiple_choice: self.multiple_choice,
+            quiz: self.quiz,
+            question: tl::types::TextWithEntities {
+                text: self.question.clone(),
+                entities: Vec::new(),
+            }
+            .into(),
+            answers: self
+                .answers
+                .iter()
+                .map(|answer| {
+                    tl::types::PollAnswer {
+                        text: tl::types::TextWithEntities {
+                            text: answer.text.clone(),
+                            entities: Vec::new(),
+                        }
+                        .into(),
+                        option: answer.option.clone(),
+                    }
+                    .into()
+                })
+                .collect(),
+            close_period: self.close_period,
+            close_date: self.close_date,
+        }
+        .into()
+    }
+}
+
+/// A poll to send with `send_poll`.
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativePollDraft {
+    pub question: String,
+    pub answers: Vec<String>,
+    pub multiple_choice: Option<bool>,
+    pub public_voters: Option<bool>,
+    /// makes the poll a quiz whose right answer is `answers[correct_answer]`
+    pub correct_answer: Option<u32>,
+    pub solution: Option<String>,
+    /// seconds after which the poll closes, 5 to 600
+    pub close_period: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativePollVoter {
+    pub voter_id: i64,
+    pub name: String,
+    /// indexes into `NativePoll.answers`
+    pub answers: Vec<u32>,
+    pub date: i64,
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativePollVoters {
+    pub count: i32,
+    pub voters: Vec<NativePollVoter>,
+    pub next_offset: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeVenue {
+    pub title: String,
+    pub address: String,
+    /// e.g. `foursquare`
+    pub provider: String,
+    pub venue_id: String,
+    pub venue_type: String,
+}
+
+/// A point on the map, with the venue or live location details if it is one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// in meters
+    pub accuracy_radius: Option<i32>,
+    pub venue: Option<NativeVenue>,
+    /// for how long a live location is shared, in seconds from the message date
+    pub live_period: Option<i32>,
+    /// the direction a live location is moving in, in degrees 1-360
+    pub heading: Option<i32>,
+    pub proximity_notification_radius: Option<i32>,
+}
+
+impl NativeLocation {
+    fn from_point(raw: &tl::enums::GeoPoint) -> Option<Self> {
+        match raw {
+            tl::enums::GeoPoint::Point(point) => Some(Self {
+                latitude: point.lat,
+                longitude: point.long,
+                accuracy_radius: point.accuracy_radius,
+                venue: None,
+                live_period: None,
+                heading: None,
+                proximity_notification_radius: None,
+            }),
+            tl::enums::GeoPoint::Empty => None,
+        }
+    }
+
+    pub fn from_media(raw: &tl::enums::MessageMedia) -> Option<Self> {
+        match raw {
+            tl::enums::MessageMedia::Geo(geo) => Self::from_point(&geo.geo),
+            tl::enums::MessageMedia::GeoLive(live) => Some(Self {
+                live_period: Some(live.period),
+                heading: live.heading,
+                proximity_notification_radius: live.proximity_notification_radius,
+                ..Self::from_point(&live.geo)?
+            }),
+            tl::enums::MessageMedia::Venue(venue) => Some(Self {
+                venue: Some(NativeVenue {
+                    title: venue.title.clone(),
+                    address: venue.address.clone(),
+                    provider: venue.provider.clone(),
+                    venue_id: venue.venue_id.clone(),
+                    venue_type: venue.venue_type.clone(),
+                }),
+                ..Self::from_point(&venue.geo)?
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn to_input_point(&self) -> tl::enums::InputGeoPoint {
+        tl::types::InputGeoPoint {
+            lat: self.latitude,
+            long: self.longitude,
+            accuracy_radius: self.accuracy_radius,
+        }
+        .into()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeContact {
+    pub phone_number: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub vcard: String,
+    /// set if the contact has a Telegram account
+    pub user_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeDice {
+    /// 🎲, 🎯, 🏀, ⚽, 🎳 or 🎰
+    pub emoticon: String,
+    /// 0 until the server rolled it
+    pub value: i32,
+}
+
+/// A voice message, or a round video message if `video` is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeVoiceNote {
+    pub video: bool,
+    /// in seconds
+    pub duration: f64,
+    /// one bar height per sample, from 0 to 31; empty for video notes
+    pub waveform: Vec<u8>,
+    /// the diameter of a video note, in pixels
+    pub length: Option<i32>,
+}
+
+impl NativeVoiceNote {
+    pub fn from_media(raw: &tl::enums::MessageMedia) -> Option<Self> {
+        let tl::enums::MessageMedia::Document(media) = raw else {
+            return None;
+        };
+        let Some(tl::enums::Document::Document(document)) = media.document.as_ref() else {
+            return None;
+        };
+        document.attributes.iter().find_map(|attribute| match attribute {
+            tl::enums::DocumentAttribute::Audio(audio) if audio.voice => Some(Self {
+                video: false,
+                duration: audio.duration as f64,
+                waveform: audio.waveform.as_deref().map(crate::tg::voice::decode_waveform).unwrap_or_default(),
+                length: None,
+            }),
+            tl::enums::DocumentAttribute::Video(video) if video.round_message => Some(Self {
+                video: true,
+                duration: video.duration,
+                waveform: Vec::new(),
+                length: Some(video.w),
+            }),
+            _ => None,
+        })
+    }
+}
+
+/// Enough to send, or download, a document again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeDocumentRef {
+    pub id: i64,
+    pub access_hash: i64,
+    pub file_reference: Vec<u8>,
+    pub dc_id: i32,
+    pub size: i64,
+}
+
+impl NativeDocumentRef {
+    pub fn from_document(raw: &tl::types::Document) -> Self {
+        Self {
+            id: raw.id,
+            access_hash: raw.access_hash,
+            file_reference: raw.file_reference.clone(),
+            dc_id: raw.dc_id,
+            size: raw.size,
+        }
+    }
+
+    pub fn to_input(&self) -> tl::enums::InputDocument {
+        tl::types::InputDocument {
+            id: self.id,
+            access_hash: self.access_hash,
+            file_reference: self.file_reference.clone(),
+        }
+        .into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[napi]
+pub enum StickerFormat {
+    Webp,
+    /// gzipped Lottie animation
+    Tgs,
+    Webm,
+}
+
+impl StickerFormat {
+    pub fn from_mime_type(mime_type: &str) -> Self {
+        match mime_type {
+            "application/x-tgsticker" => Self::Tgs,
+            "video/webm" => Self::Webm,
+            _ => Self::Webp,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Webp => "webp",
+            Self::Tgs => "tgs",
+            Self::Webm => "webm",
+        }
+    }
+}
+
+/// Either an id and access hash, or a short name as in `t.me/addstickers/<short_name>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeStickerSetRef {
+    pub id: Option<i64>,
+    pub access_hash: Option<i64>,
+    pub short_name: Option<String>,
+}
+
+impl NativeStickerSetRef {
+    pub fn from_raw(raw: &tl::enums::InputStickerSet) -> Option<Self> {
+        match raw {
+            tl::enums::InputStickerSet::Id(set) => Some(Self {
+                id: Some(set.id),
+                access_hash: Some(set.access_hash),
+                short_name: None,
+            }),
+            tl::enums::InputStickerSet::ShortName(set) => Some(Self {
+                id: None,
+                access_hash: None,
+                short_name: Some(set.short_name.clone()),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn to_input(&self) -> tl::enums::InputStickerSet {
+        match (self.id, self.access_hash, self.short_name.as_ref()) {
+            (Some(id), Some(access_hash), _) => tl::types::InputStickerSetId { id, access_hash }.into(),
+            (_, _, Some(short_name)) => tl::types::InputStickerSetShortName { short_name: short_name.clone() }.into(),
+            _ => tl::enums::InputStickerSet::Empty,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeSticker {
+    pub document: NativeDocumentRef,
+    pub emoji: String,
+    pub set: Option<NativeStickerSetRef>,
+    pub format: StickerFormat,
+    /// a custom emoji, as used in message entities
+    pub custom_emoji: bool,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl NativeSticker {
+    pub fn from_document(raw: &tl::types::Document) -> Option<Self> {
+        let mut sticker: Option<Self> = None;
+        let (mut width, mut height) = (0, 0);
+        for attribute in raw.attributes.iter() {
+            let (emoji, set, custom_emoji) = match attribute {
+                tl::enums::DocumentAttribute::Sticker(attribute) => (&attribute.alt, &attribute.stickerset, false),
+                tl::enums::DocumentAttribute::CustomEmoji(attribute) => (&attribute.alt, &attribute.stickerset, true),
+                tl::enums::DocumentAttribute::ImageSize(size) => {
+                    (width, height) = (size.w, size.h);
+                    continue;
+                }
+                tl::enums::DocumentAttribute::Video(video) => {
+                    (width, height) = (video.w, video.h);
+                    continue;
+                }
+                _ => continue,
+            };
+            sticker = Some(Self {
+                document: NativeDocumentRef::from_document(raw),
+                emoji: emoji.clone(),
+                set: NativeStickerSetRef::from_raw(set),
+                format: StickerFormat::from_mime_type(&raw.mime_type),
+                custom_emoji,
+                width: 0,
+                height: 0,
+            });
+        }
+        sticker.map(|sticker| Self { width, height, ..sticker })
+    }
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeStickerSet {
+    pub set: NativeStickerSetRef,
+    pub title: String,
+    pub count: i32,
+    pub archived: bool,
+    pub official: bool,
+    pub masks: bool,
+    /// a set of custom emoji
+    pub emojis: bool,
+    pub installed_date: Option<i32>,
+    /// only filled when the set is fetched on its own
+    pub stickers: Vec<NativeSticker>,
+}
+
+impl NativeStickerSet {
+    pub fn from_raw(raw: &tl::enums::StickerSet) -> Self {
+        let tl::enums::StickerSet::Set(raw) = raw;
+        Self {
+            set: NativeStickerSetRef {
+                id: Some(raw.id),
+                access_hash: Some(raw.access_hash),
+                short_name: Some(raw.short_name.clone()),
+            },
+            title: raw.title.clone(),
+            count: raw.count,
+            archived: raw.archived,
+            official: raw.official,
+            masks: raw.masks,
+            emojis: raw.emojis,
+            installed_date: raw.installed_date,
+            stickers: Vec::new(),
+        }
+    }
+}
+
+/// A GIF, which Telegram stores as a silent MP4.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeAnimation {
+    pub document: NativeDocumentRef,
+    pub mime_type: String,
+    /// in seconds
+    pub duration: f64,
+    pub width: i32,
+    pub height: i32,
+    pub stripped_thumb: Option<Vec<u8>>,
+}
+
+impl NativeAnimation {
+    pub fn from_document(raw: &tl::types::Document) -> Option<Self> {
+        let mut animated = false;
+        let (mut duration, mut width, mut height) = (0f64, 0, 0);
+        for attribute in raw.attributes.iter() {
+            match attribute {
+                tl::enums::DocumentAttribute::Animated => animated = true,
+                // animated stickers are stickers first
+                tl::enums::DocumentAttribute::Sticker(_) | tl::enums::DocumentAttribute::CustomEmoji(_) => return None,
+                tl::enums::DocumentAttribute::Video(video) => {
+                    (duration, width, height) = (video.duration, video.w, video.h);
+                }
+                tl::enums::DocumentAttribute::ImageSize(size) => (width, height) = (size.w, size.h),
+                _ => {}
+            }
+        }
+        animated.then(|| Self {
+            document: NativeDocumentRef::from_document(raw),
+            mime_type: raw.mime_type.clone(),
+            duration,
+            width,
+            height,
+            stripped_thumb: raw.thumbs.as_deref().and_then(stripped_thumb),
+        })
+    }
+}
+
+/// The link preview of a message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeWebPage {
+    pub url: String,
+    pub display_url: String,
+    /// the server is still fetching the page, it arrives later as an edit
+    pub pending: bool,
+    /// e.g. "article", "photo" or "video"
+    pub page_type: Option<String>,
+    pub site_name: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub photo_thumb: Option<Vec<u8>>,
+    /// whether the photo is shown large rather than as a small thumbnail
+    pub large_media: bool,
+    /// whether the preview is shown above the text
+    pub above_text: bool,
+}
+
+impl NativeWebPage {
+    pub fn from_media(raw: &tl::types::MessageMediaWebPage) -> Option<Self> {
+        let empty = |url: &str, pending: bool| Self {
+            url: url.to_string(),
+            display_url: url.to_string(),
+            pending,
+            page_type: None,
+            site_name: None,
+            title: None,
+            description: None,
+            photo_thumb: None,
+            large_media: false,
+            above_text: false,
+        };
+        match &raw.webpage {
+            tl::enums::WebPage::Page(page) => Some(Self {
+                url: page.url.clone(),
+                display_url: page.display_url.clone(),
+                pending: false,
+                page_type: page.r#type.clone(),
+                site_name: page.site_name.clone(),
+                title: page.title.clone(),
+                description: page.description.clone(),
+                photo_thumb: match &page.photo {
+                    Some(tl::enums::Photo::Photo(photo)) => stripped_thumb(&photo.sizes),
+                    _ => None,
+                },
+                large_media: raw.force_large_media || (page.has_large_media && !raw.force_small_media),
+                above_text: false,
+            }),
+            tl::enums::WebPage::Pending(page) => Some(empty(page.url.as_deref().unwrap_or_default(), true)),
+            tl::enums::WebPage::Empty(page) => page.url.as_deref().map(|url| empty(url, false)),
+            tl::enums::WebPage::NotModified(_) => None,
+        }
+    }
+}
+
+/// How to show the link preview of a message being sent.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeLinkPreviewOptions {
+    /// send without a preview
+    pub disabled: Option<bool>,
+    /// the link to preview, the first one of the text if not set
+    pub url: Option<String>,
+    /// show the preview above the text
+    pub above_text: Option<bool>,
+    /// force a large (true) or a small (false) photo, the server decides if not set
+    pub large_media: Option<bool>,
+}
+
+/// The unfinished message of a chat, synced across my devices.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeDraft {
+    pub text: String,
+    pub date: i64,
+    pub reply_to_message_id: Option<i32>,
+}
+
+impl NativeDraft {
+    pub fn from_raw(raw: &tl::enums::DraftMessage) -> Option<Self> {
+        match raw {
+            tl::enums::DraftMessage::Draft(draft) => Some(Self {
+                text: draft.message.clone(),
+                date: draft.date as i64,
+                reply_to_message_id: match &draft.reply_to {
+                    Some(tl::enums::InputReplyTo::Message(reply_to)) => Some(reply_to.reply_to_msg_id),
+                    _ => None,
+                },
+            }),
+            tl::enums::DraftMessage::Empty(_) => None,
+        }
+    }
+
+    pub fn from_dialog(raw: &tl::enums::Dialog) -> Option<Self> {
+        match raw {
+            tl::enums::Dialog::Dialog(dialog) => dialog.draft.as_ref().and_then(Self::from_raw),
+            tl::enums::Dialog::Folder(_) => None,
+        }
+    }
+}
+
+/// The `schedule_date` Telegram reads as "when the recipient comes online".
+pub(crate) const SCHEDULE_WHEN_ONLINE: i32 = 0x7FFFFFFE;
+
+/// The unix time `date` as the `schedule_date` Telegram takes, which is 32 bits.
+pub(crate) fn checked_schedule_date(date: i64) -> anyhow::Result<i32> {
+    i32::try_from(date)
+        .ok()
+        .filter(|date| *date > 0 && *date < SCHEDULE_WHEN_ONLINE)
+        .ok_or_else(|| anyhow::anyhow!("Invalid schedule date {date}"))
+}
+
+/// How to send a message, sending it now with a notification if nothing is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NativeSendOptions {
+    /// deliver without a notification sound
+    pub silent: Option<bool>,
+    /// unix time at which the server sends the message
+    pub schedule_date: Option<i64>,
+    /// send once the recipient comes online, private chats only
+    pub when_online: Option<bool>,
+    /// text messages only, the preview is shown as the server decides if not set
+    pub link_preview: Option<NativeLinkPreviewOptions>,
+}
+
+impl NativeSendOptions {
+    pub fn silent(&self) -> bool {
+        self.silent.unwrap_or(false)
+    }
+
+    pub fn schedule_date(&self) -> anyhow::Result<Option<i32>> {
+        if self.when_online.unwrap_or(false) {
+            return Ok(Some(SCHEDULE_WHEN_ONLINE));
+        }
+        self.schedule_date.map(checked_schedule_date).transpose()
+    }
+
+    pub fn no_webpage(&self) -> bool {
+        self.link_preview.as_ref().and_then(|preview| preview.disabled).unwrap_or(false)
+    }
+
+    /// Whether the link preview goes above the text.
+    pub fn invert_media(&self) -> bool {
+        !self.no_webpage() && self.link_preview.as_ref().and_then(|preview| preview.above_text).unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[napi]
+pub enum ChatActionKind {
+    Typing,
+    /// stop showing my previous action
+    Cancel,
+    RecordVideo,
+    UploadVideo,
+    RecordAudio,
+    UploadAudio,
+    UploadPhoto,
+    UploadDocument,
+    GeoLocation,
+    ChooseContact,
+    RecordRound,
+    UploadRound,
+    ChooseSticker,
+    /// e.g. playing a game or speaking in a group call
+    Other,
+}
+
+/// What someone is doing in a chat, shown in place of its status.
+#[derive(Debug, Clone, PartialEq)]
+#[napi(object)]
+pub struct NativeChatAction {
+    pub kind: ChatActionKind,
+    /// in percents, for uploads only
+    pub progress: Option<i32>,
+}
+
+impl NativeChatAction {
+    pub fn from_raw(raw: &tl::enums::SendMessageAction) -> Self {
+        use tl::enums::SendMessageAction as Action;
+        let (kind, progress) = match raw {
+            Action::SendMessageTypingAction => (ChatActionKind::Typing, None),
+            Action::SendMessageCancelAction => (ChatActionKind::Cancel, None),
+            Action::SendMessageRecordVideoAction => (ChatActionKind::RecordVideo, None),
+            Action::SendMessageUploadVideoAction(action) => (ChatActionKind::UploadVideo, Some(action.progress)),
+            Action::SendMessageRecordAudioAction => (ChatActionKind::RecordAudio, None),
+            Action::SendMessageUploadAudioAction(action) => (ChatActionKind::UploadAudio, Some(action.progress)),
+            Action::SendMessageUploadPhotoAction(action) => (ChatActionKind::UploadPhoto, Some(action.progress)),
+            Action::SendMessageUploadDocumentAction(action) => (ChatActionKind::UploadDocument, Some(action.progress)),
+            Action::SendMessageGeoLocationAction => (ChatActionKind::GeoLocation, None),
+            Action::SendMessageChooseContactAction => (ChatActionKind::ChooseContact, None),
+            Action::SendMessageRecordRoundAction => (ChatActionKind::RecordRound, None),
+            Action::SendMessageUploadRoundAction(action) => (ChatActionKind::UploadRound, Some(action.progress)),
+            Action::SendMessageChooseStickerAction => (ChatActionKind::ChooseSticker, None),
+            _ => (ChatActionKind::Other, None),
+        };
+        Self { kind, progress }
+    }
+
+    pub fn to_raw(&self) -> anyhow::Result<tl::enums::SendMessageAction> {
+        use tl::enums::SendMessageAction as Action;
+        let progress = self.progress.unwrap_or(0);
+        Ok(match self.kind {
+            ChatActionKind::Typing => Action::SendMessageTypingAction,
+            ChatActionKind::Cancel => Action::SendMessageCancelAction,
+            ChatActionKind::RecordVideo => Action::SendMessageRecordVideoAction,
+            ChatActionKind::UploadVideo => tl::types::SendMessageUploadVideoAction { progress }.into(),
+            ChatActionKind::RecordAudio => Action::SendMessageRecordAudioAction,
+            ChatActionKind::UploadAudio => tl::types::SendMessageUploadAudioAction { progress }.into(),
+            ChatActionKind::UploadPhoto => tl::types::SendMessageUploadPhotoAction { progress }.into(),
+            ChatActionKind::UploadDocument => tl::types::SendMessageUploadDocumentAction { progress }.into(),
+            ChatActionKind::GeoLocation => Action::SendMessageGeoLocationAction,
+            ChatActionKind::ChooseContact => Action::SendMessageChooseContactAction,
+            ChatActionKind::RecordRound => Action::SendMessageRecordRoundAction,
+            ChatActionKind::UploadRound => tl::types::SendMessageUploadRoundAction { progress }.into(),
+            ChatActionKind::ChooseSticker => Action::SendMessageChooseStickerAction,
+            // only reported by others, for actions we cannot start from here
+            ChatActionKind::Other => return Err(anyhow::anyhow!("Cannot send the Other chat action")),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+#[napi(object)]
+pub struct NativeTypingUser {
+    pub user_id: i64,
+    pub name: String,
+    pub action: NativeChatAction,
+}
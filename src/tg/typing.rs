@@ -0,0 +1,145 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{ChatActionKind, NativeChatAction, NativeTypingUser};
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use log::{debug, error};
+use napi_ohos::threadsafe_function::ThreadsafeFunctionCallMode;
+use napi_ohos::tokio;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Clients repeat their action while it lasts, one not repeated for this long is over.
+const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
+/// How often we repeat our own upload action while a file is being uploaded.
+pub(crate) const CHAT_ACTION_REPEAT: Duration = Duration::from_secs(5);
+
+/// Someone doing something in a chat, until `expires_at` unless repeated.
+pub(crate) struct TypingUser {
+    user_id: i64,
+    action: NativeChatAction,
+    expires_at: Instant,
+}
+
+impl Backend {
+    /// Show `action` to the others in the chat, `Cancel` stops showing it.
+    pub async fn send_chat_action(&self, chat_id: i64, action: NativeChatAction) -> Result<()> {
+        let request = tl::functions::messages::SetTyping {
+            peer: self.packed_chat(chat_id)?.to_input_peer(),
+            top_msg_id: None,
+            action: action.to_raw()?,
+        };
+        scheduled!(self, "messages.setTyping", self.client().invoke(&request))?;
+        Ok(())
+    }
+
+    /// Report the progress of an upload as a chat action, failures don't matter much.
+    pub(crate) async fn send_upload_action(&self, chat_id: i64, kind: ChatActionKind, progress: i32) {
+        let action = NativeChatAction {
+            kind,
+            progress: Some(progress),
+        };
+        if let Err(e) = self.send_chat_action(chat_id, action).await {
+            error!("send_upload_action Failed to report {kind:?} in {chat_id}: {e}");
+        }
+    }
+
+    /// Apply an updateUserTyping, updateChatUserTyping or updateChannelUserTyping.
+    pub(crate) fn typing_update_handler(&'static self, chat_id: i64, user_id: i64, action: &tl::enums::SendMessageAction) {
+        let action = NativeChatAction::from_raw(action);
+        debug!("typing_update_handler {user_id} in {chat_id}: {:?}", action);
+        let mut users = self.typing_users.entry(chat_id).or_default();
+        users.retain(|user| user.user_id != user_id);
+        if action.kind != ChatActionKind::Cancel {
+            users.push(TypingUser {
+                user_id,
+                action,
+                expires_at: Instant::now() + TYPING_TIMEOUT,
+            });
+        }
+        drop(users);
+        if self.typing_timers.insert(chat_id) {
+            tokio::spawn(self.run_typing_timer(chat_id));
+        }
+        self.emit_typing(chat_id);
+    }
+
+    /// Expire the typing users of the chat as their time comes, for as long as there
+    /// are some. There is one such timer per chat, see `typing_timers`.
+    async fn run_typing_timer(&'static self, chat_id: i64) {
+        loop {
+            let next_expiry = self
+                .typing_users
+                .get(&chat_id)
+                .and_then(|users| users.iter().map(|user| user.expires_at).min());
+            match next_expiry {
+                Some(next_expiry) => {
+                    tokio::time::sleep_until(next_expiry).await;
+                    self.expire_typing(chat_id);
+                }
+                None => {
+                    self.typing_timers.remove(&chat_id);
+                    // someone may have started typing right before the timer was removed
+                    let has_users = self.typing_users.get(&chat_id).is_some_and(|users| !users.is_empty());
+                    if !has_users || !self.typing_timers.insert(chat_id) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// A message ends whatever its sender was doing to write it.
+    pub(crate) fn stop_typing(&self, chat_id: i64, user_id: i64) {
+        let removed = match self.typing_users.get_mut(&chat_id) {
+            Some(mut users) => {
+                let before = users.len();
+                users.retain(|user| user.user_id != user_id);
+                users.len() != before
+            }
+            None => false,
+        };
+        if removed {
+            self.emit_typing(chat_id);
+        }
+    }
+
+    fn expire_typing(&self, chat_id: i64) {
+        let now = Instant::now();
+        let expired = match self.typing_users.get_mut(&chat_id) {
+            Some(mut users) => {
+                let before = users.len();
+                users.retain(|user| user.expires_at > now);
+                users.len() != before
+            }
+            None => false,
+        };
+        if expired {
+            self.emit_typing(chat_id);
+        }
+    }
+
+    fn emit_typing(&self, chat_id: i64) {
+        let users: Vec<NativeTypingUser> = match self.typing_users.get(&chat_id) {
+            Some(users) => users
+                .iter()
+                .map(|user| NativeTypingUser {
+                    user_id: user.user_id,
+                    name: self
+                        .seen_chats_map
+                        .get(&user.user_id)
+                        .map(|chat| chat.full_name.clone())
+                        .unwrap_or_default(),
+                    action: user.action.clone(),
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        if users.is_empty() {
+            self.typing_users.remove_if(&chat_id, |_, users| users.is_empty());
+        }
+        if let Some(cb) = self.chat_typing_callback.as_ref() {
+            cb.call(Ok((chat_id, users)), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+}
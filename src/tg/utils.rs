@@ -1,7 +1,12 @@
+use crate::tg::scheduler::RequestError;
 use crate::tg::BASE_PATH;
 use const_format::concatcp;
+use dashmap::DashMap;
+use grammers_client::grammers_tl_types as tl;
 use napi_derive_ohos::napi;
 use napi_ohos::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -50,3 +55,68 @@ pub fn get_profile_photo_path_and_count(chat_id: i64) -> Result<ProfilePhotoPath
         count: n_profile_photos as i32,
     })
 }
+
+/// The bare id of the user, group or channel `peer` points to, as used in our maps.
+pub(crate) fn peer_id(peer: &tl::enums::Peer) -> i64 {
+    match peer {
+        tl::enums::Peer::User(user) => user.user_id,
+        tl::enums::Peer::Chat(chat) => chat.chat_id,
+        tl::enums::Peer::Channel(channel) => channel.channel_id,
+    }
+}
+
+/// A `random_id` for the requests sending something, so that resending is idempotent.
+pub(crate) fn random_id() -> anyhow::Result<i64> {
+    let mut bytes = [0u8; 8];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(i64::from_le_bytes(bytes))
+}
+
+/// The number of seconds Telegram asked us to wait, if `e` is a `FLOOD_WAIT_X`.
+pub(crate) fn flood_wait_seconds(e: &anyhow::Error) -> Option<u32> {
+    e.downcast_ref::<grammers_mtsender::InvocationError>()
+        .and_then(|e| e.flood_wait_seconds())
+}
+
+/// Whether retrying the request that failed with `e` later could succeed, as opposed
+/// to errors caused by the request itself (bad peer, missing file, ...).
+pub(crate) fn is_transient_error(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<grammers_mtsender::InvocationError>() {
+        Some(grammers_mtsender::InvocationError::Rpc(rpc)) => {
+            rpc.code == 420 || rpc.code >= 500 || flood_wait_seconds(e).is_some()
+        }
+        Some(_) => true,
+        None => e.downcast_ref::<std::io::Error>().is_some_and(|e| {
+            !matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied)
+        }),
+    }
+}
+
+/// Serializes the writers of the same path in [`write_atomically`].
+static WRITE_LOCKS: LazyLock<DashMap<String, Arc<Mutex<()>>>> = LazyLock::new(DashMap::new);
+static TMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Replace `path` with `data` so that a crash (or a kill) at any point leaves either the
+/// old or the new file behind, never a torn one: write a temporary file next to it,
+/// fsync it, then rename it over `path`.
+pub(crate) fn write_atomically(path: &str, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let lock = WRITE_LOCKS.entry(path.to_string()).or_default().clone();
+    let _guard = lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let tmp_path = format!(
+        "{path}.{}.{}.tmp",
+        std::process::id(),
+        TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+    // make the rename itself durable
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
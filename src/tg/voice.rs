@@ -0,0 +1,316 @@
+use crate::tg::types::{NativeMessage, UpdateUploadProgressCallback};
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use log::debug;
+use std::sync::Arc;
+
+/// How many bars the waveform we send has, as official clients do.
+const WAVEFORM_SAMPLES: usize = 100;
+const WAVEFORM_MAX: u8 = 31;
+/// Opus granule positions always count 48 kHz samples.
+const OPUS_SAMPLE_RATE: f64 = 48000.0;
+
+/// Unpack the 5-bit values Telegram packs waveforms into.
+pub(crate) fn decode_waveform(packed: &[u8]) -> Vec<u8> {
+    (0..packed.len() * 8 / 5)
+        .map(|i| {
+            let bit = i * 5;
+            let low = packed[bit / 8] as u16;
+            let high = packed.get(bit / 8 + 1).copied().unwrap_or(0) as u16;
+            (((high << 8 | low) >> (bit % 8)) & WAVEFORM_MAX as u16) as u8
+        })
+        .collect()
+}
+
+pub(crate) fn encode_waveform(samples: &[u8]) -> Vec<u8> {
+    let mut packed = vec![0u8; (samples.len() * 5).div_ceil(8)];
+    for (i, &sample) in samples.iter().enumerate() {
+        let bit = i * 5;
+        let value = ((sample.min(WAVEFORM_MAX)) as u16) << (bit % 8);
+        packed[bit / 8] |= value as u8;
+        if let Some(next) = packed.get_mut(bit / 8 + 1) {
+            *next |= (value >> 8) as u8;
+        }
+    }
+    packed
+}
+
+/// What we learn from an OGG/Opus file without decoding it.
+struct OpusInfo {
+    duration: f64,
+    waveform: Vec<u8>,
+}
+
+/// Split an OGG stream into its packets, ignoring page boundaries.
+fn ogg_packets(data: &[u8]) -> Result<(Vec<Vec<u8>>, i64)> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    let mut last_granule = 0i64;
+    let mut offset = 0;
+    while offset + 27 <= data.len() {
+        if &data[offset..offset + 4] != b"OggS" {
+            return Err(anyhow::anyhow!("Not an OGG stream at byte {offset}"));
+        }
+        let granule = i64::from_le_bytes(data[offset + 6..offset + 14].try_into().unwrap());
+        if granule >= 0 {
+            last_granule = granule;
+        }
+        let segments = data[offset + 26] as usize;
+        let table = data
+            .get(offset + 27..offset + 27 + segments)
+            .ok_or_else(|| anyhow::anyhow!("Truncated OGG page"))?;
+        let mut body = offset + 27 + segments;
+        for &lacing in table {
+            let segment = data
+                .get(body..body + lacing as usize)
+                .ok_or_else(|| anyhow::anyhow!("Truncated OGG page"))?;
+            packet.extend_from_slice(segment);
+            body += lacing as usize;
+            // a segment shorter than 255 bytes ends the packet
+            if lacing < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+        offset = body;
+    }
+    Ok((packets, last_granule))
+}
+
+/// Read the duration of an OGG/Opus file and estimate its waveform. Opus is VBR, so
+/// the size of a packet follows the loudness of the 20 ms it encodes closely enough to
+/// draw bars without decoding the audio.
+fn parse_opus(data: &[u8]) -> Result<OpusInfo> {
+    let (packets, last_granule) = ogg_packets(data)?;
+    let head = packets
+        .first()
+        .filter(|head| head.starts_with(b"OpusHead") && head.len() >= 12)
+        .ok_or_else(|| anyhow::anyhow!("Not an Opus stream"))?;
+    let pre_skip = u16::from_le_bytes([head[10], head[11]]) as i64;
+    let duration = (last_granule - pre_skip).max(0) as f64 / OPUS_SAMPLE_RATE;
+
+    // skip OpusHead and OpusTags
+    let sizes: Vec<usize> = packets.iter().skip(2).map(|packet| packet.len()).collect();
+    let mut waveform = vec![0u8; WAVEFORM_SAMPLES];
+    if !sizes.is_empty() {
+        let buckets: Vec<f64> = (0..WAVEFORM_SAMPLES)
+            .map(|i| {
+                let start = i * sizes.len() / WAVEFORM_SAMPLES;
+                let end = ((i + 1) * sizes.len() / WAVEFORM_SAMPLES).max(start + 1).min(sizes.len());
+                let bucket = &sizes[start.min(end - 1)..end];
+                bucket.iter().sum::<usize>() as f64 / bucket.len() as f64
+            })
+            .collect();
+        let min = buckets.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = buckets.iter().cloned().fold(0f64, f64::max);
+        if max > min {
+            for (sample, bucket) in waveform.iter_mut().zip(buckets) {
+                *sample = ((bucket - min) / (max - min) * WAVEFORM_MAX as f64).round() as u8;
+            }
+        }
+    }
+    Ok(OpusInfo { duration, waveform })
+}
+
+/// Iterate over the MP4 boxes in `data` as `(type, body)`.
+fn mp4_boxes(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let header = data.get(offset..offset + 8)?;
+        let mut size = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let kind = &header[4..8];
+        let mut body = offset + 8;
+        if size == 1 {
+            size = u64::from_be_bytes(data.get(offset + 8..offset + 16)?.try_into().unwrap()) as usize;
+            body += 8;
+        } else if size == 0 {
+            size = data.len() - offset;
+        }
+        let end = offset.checked_add(size).filter(|&end| end <= data.len() && end >= body)?;
+        offset = end;
+        Some((kind, &data[body..end]))
+    })
+}
+
+fn mp4_child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    mp4_boxes(data).find(|(k, _)| *k == kind).map(|(_, body)| body)
+}
+
+pub(crate) struct Mp4Info {
+    /// in seconds
+    pub(crate) duration: f64,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+}
+
+/// Read the duration and the size of the video track of an MP4 file.
+pub(crate) fn parse_mp4(data: &[u8]) -> Result<Mp4Info> {
+    let moov = mp4_child(data, b"moov").ok_or_else(|| anyhow::anyhow!("No moov box, not an MP4 file"))?;
+    let mvhd = mp4_child(moov, b"mvhd").ok_or_else(|| anyhow::anyhow!("No mvhd box"))?;
+    let be32 = |bytes: &[u8], at: usize| bytes.get(at..at + 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()));
+    let be64 = |bytes: &[u8], at: usize| bytes.get(at..at + 8).map(|b| u64::from_be_bytes(b.try_into().unwrap()));
+    let (timescale, duration) = match mvhd.first() {
+        Some(1) => (be32(mvhd, 20), be64(mvhd, 24)),
+        _ => (be32(mvhd, 12), be32(mvhd, 16).map(u64::from)),
+    };
+    let (timescale, duration) = timescale
+        .zip(duration)
+        .filter(|(timescale, _)| *timescale != 0)
+        .ok_or_else(|| anyhow::anyhow!("Malformed mvhd box"))?;
+    let (width, height) = mp4_boxes(moov)
+        .filter(|(kind, _)| *kind == b"trak")
+        .find_map(|(_, trak)| {
+            let hdlr = mp4_child(mp4_child(trak, b"mdia")?, b"hdlr")?;
+            if hdlr.get(8..12)? != b"vide" {
+                return None;
+            }
+            let tkhd = mp4_child(trak, b"tkhd")?;
+            let at = if tkhd.first() == Some(&1) { 88 } else { 76 };
+            // 16.16 fixed point
+            Some(((be32(tkhd, at)? >> 16) as i32, (be32(tkhd, at + 4)? >> 16) as i32))
+        })
+        .ok_or_else(|| anyhow::anyhow!("No video track"))?;
+    Ok(Mp4Info {
+        duration: duration as f64 / timescale as f64,
+        width,
+        height,
+    })
+}
+
+impl Backend {
+    /// Send the OGG/Opus file at `path` as a voice message. Its waveform is an
+    /// approximation from the packet sizes, see [`parse_opus`].
+    pub async fn send_voice(
+        &self,
+        chat_id: i64,
+        path: String,
+        caption: String,
+        update_upload_progress_callback: Option<Arc<UpdateUploadProgressCallback>>,
+    ) -> Result<Vec<NativeMessage>> {
+        let info = parse_opus(&std::fs::read(&path)?)?;
+        debug!("send_voice {path}: {:.1}s", info.duration);
+        let attribute = tl::types::DocumentAttributeAudio {
+            voice: true,
+            duration: info.duration.round() as i32,
+            title: None,
+            performer: None,
+            waveform: Some(encode_waveform(&info.waveform)),
+        };
+        self.send_uploaded_document(chat_id, &path, "audio/ogg", vec![attribute.into()], false, caption, update_upload_progress_callback)
+            .await
+    }
+
+    /// Send the square MP4 file at `path` as a round video message.
+    pub async fn send_video_note(
+        &self,
+        chat_id: i64,
+        path: String,
+        update_upload_progress_callback: Option<Arc<UpdateUploadProgressCallback>>,
+    ) -> Result<Vec<NativeMessage>> {
+        let Mp4Info { duration, width: length, .. } = parse_mp4(&std::fs::read(&path)?)?;
+        debug!("send_video_note {path}: {duration:.1}s, {length}px");
+        let attribute = tl::types::DocumentAttributeVideo {
+            round_message: true,
+            supports_streaming: true,
+            nosound: false,
+            duration,
+            w: length,
+            h: length,
+            preload_prefix_size: None,
+            video_start_ts: None,
+        };
+        self.send_uploaded_document(chat_id, &path, "video/mp4", vec![attribute.into()], false, String::new(), update_upload_progress_callback)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An OGG page holding `packets`, each shorter than 255 bytes.
+    fn ogg_page(granule: i64, packets: &[&[u8]]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.extend_from_slice(&[0, 0]);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&[0; 12]);
+        page.push(packets.len() as u8);
+        page.extend(packets.iter().map(|packet| packet.len() as u8));
+        packets.iter().for_each(|packet| page.extend_from_slice(packet));
+        page
+    }
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    /// A moov box with `mvhd` and a 240x240 video track.
+    fn mp4(mvhd: Vec<u8>) -> Vec<u8> {
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"vide");
+        hdlr.extend_from_slice(&[0; 12]);
+        let mut tkhd = vec![0u8; 76];
+        tkhd.extend_from_slice(&(240u32 << 16).to_be_bytes());
+        tkhd.extend_from_slice(&(240u32 << 16).to_be_bytes());
+        let mdia = mp4_box(b"mdia", &mp4_box(b"hdlr", &hdlr));
+        let trak = mp4_box(b"trak", &[mp4_box(b"tkhd", &tkhd), mdia].concat());
+        let moov = mp4_box(b"moov", &[mp4_box(b"mvhd", &mvhd), trak].concat());
+        [mp4_box(b"ftyp", b"isom"), moov].concat()
+    }
+
+    #[test]
+    fn waveform_round_trip() {
+        let samples: Vec<u8> = (0..WAVEFORM_SAMPLES).map(|i| (i * 7 % 32) as u8).collect();
+        let packed = encode_waveform(&samples);
+        assert_eq!(packed.len(), 63);
+        assert_eq!(decode_waveform(&packed), samples);
+    }
+
+    #[test]
+    fn opus_duration() {
+        let mut head = b"OpusHead".to_vec();
+        head.extend_from_slice(&[1, 1]);
+        head.extend_from_slice(&312u16.to_le_bytes());
+        let data = [
+            ogg_page(0, &[&head]),
+            ogg_page(0, &[b"OpusTags"]),
+            ogg_page(48000 + 312, &[&[0; 10], &[0; 40]]),
+        ]
+        .concat();
+        let info = parse_opus(&data).unwrap();
+        assert_eq!(info.duration, 1.0);
+        assert_eq!(info.waveform.len(), WAVEFORM_SAMPLES);
+    }
+
+    #[test]
+    fn truncated_ogg_page() {
+        let mut data = ogg_page(0, &[b"OpusHead"]);
+        data.truncate(data.len() - 3);
+        let e = ogg_packets(&data).unwrap_err();
+        assert_eq!(e.to_string(), "Truncated OGG page");
+    }
+
+    #[test]
+    fn mvhd_v0() {
+        let mut mvhd = vec![0u8; 12];
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&2500u32.to_be_bytes());
+        let info = parse_mp4(&mp4(mvhd)).unwrap();
+        assert_eq!(info.duration, 2.5);
+        assert_eq!((info.width, info.height), (240, 240));
+    }
+
+    #[test]
+    fn mvhd_v1() {
+        let mut mvhd = vec![1u8, 0, 0, 0];
+        mvhd.extend_from_slice(&[0; 16]);
+        mvhd.extend_from_slice(&600u32.to_be_bytes());
+        mvhd.extend_from_slice(&1800u64.to_be_bytes());
+        let info = parse_mp4(&mp4(mvhd)).unwrap();
+        assert_eq!(info.duration, 3.0);
+        assert_eq!((info.width, info.height), (240, 240));
+    }
+}
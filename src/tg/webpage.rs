@@ -0,0 +1,55 @@
+use crate::tg::scheduler::scheduled;
+use crate::tg::types::{NativeSendOptions, NativeWebPage};
+use crate::tg::Backend;
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use log::debug;
+
+/// The first link of `text`, to preview when the app doesn't pick one.
+fn first_url(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .map(|word| word.trim_end_matches(|c: char| matches!(c, '.' | ',' | ')' | '!' | '?' | ';' | ':')))
+        .find(|word| word.starts_with("https://") || word.starts_with("http://"))
+}
+
+/// The `inputMediaWebPage` to send `text` with, if `options` ask for a preview only an
+/// explicit one can give: a given link, or a forced size of the photo.
+pub(crate) fn link_preview_media(text: &str, options: &NativeSendOptions) -> Result<Option<tl::enums::InputMedia>> {
+    let Some(preview) = options.link_preview.as_ref() else {
+        return Ok(None);
+    };
+    if options.no_webpage() || (preview.url.is_none() && preview.large_media.is_none()) {
+        return Ok(None);
+    }
+    let url = preview
+        .url
+        .clone()
+        .or_else(|| first_url(text).map(str::to_string))
+        .ok_or_else(|| anyhow::anyhow!("No link to preview"))?;
+    debug!("link_preview_media {url}, large: {:?}", preview.large_media);
+    Ok(Some(
+        tl::types::InputMediaWebPage {
+            force_large_media: preview.large_media == Some(true),
+            force_small_media: preview.large_media == Some(false),
+            optional: true,
+            url,
+        }
+        .into(),
+    ))
+}
+
+impl Backend {
+    /// Preview the first link of `text` for the composer. `None` if there is no link or
+    /// the page has no preview. A `pending` preview is still being fetched by the server,
+    /// ask again a bit later.
+    pub async fn get_web_preview(&self, text: String) -> Result<Option<NativeWebPage>> {
+        let request = tl::functions::messages::GetWebPagePreview {
+            message: text,
+            entities: None,
+        };
+        match scheduled!(self, "messages.getWebPagePreview", self.client().invoke(&request))? {
+            tl::enums::MessageMedia::WebPage(media) => Ok(NativeWebPage::from_media(&media)),
+            _ => Ok(None),
+        }
+    }
+}